@@ -0,0 +1,44 @@
+//! Records how the benchmarks were built, for the run manifests in `metadata.rs`
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+
+    let git_commit = output("git", &["describe", "--always", "--dirty", "--abbrev=40"]);
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let rustc_version = output(&rustc, &["--version"]);
+
+    // `-C target-cpu=native` and the like, the compiler's default for the target otherwise
+    let rustflags = std::env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let target_cpu = rustflags
+        .split('\x1f')
+        .map(|flag| flag.trim_start_matches("-C"))
+        .find_map(|codegen| codegen.strip_prefix("target-cpu="))
+        .unwrap_or("default");
+
+    println!("cargo:rustc-env=BUILD_GIT_COMMIT={git_commit}");
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={rustc_version}");
+    println!(
+        "cargo:rustc-env=BUILD_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+    println!("cargo:rustc-env=BUILD_TARGET_CPU={target_cpu}");
+    println!(
+        "cargo:rustc-env=BUILD_TARGET_FEATURES={}",
+        std::env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default()
+    );
+}
+
+/// The trimmed stdout of the command, `unknown` if it can't be run, e.g. outside a git checkout
+fn output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|stdout| stdout.trim().to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
@@ -1,5 +1,5 @@
 use rand::{Rng, SeedableRng};
-use std::num::Wrapping;
+use rust_perf_comp::kernel::{Branch, GenericKernel};
 
 const N: usize = 10_000_000;
 
@@ -20,26 +20,7 @@ pub fn main() {
         // bools[i] = i % 2 == 0;
     }
 
-    let a = do_branching_work(&v1, &v2, &bools);
+    let a = Branch::sum(&v1, &v2, &bools);
 
-    if a == Wrapping(0) {
-        println!("Zero");
-    }
-}
-
-#[inline(never)]
-fn do_branching_work(v1: &[u32], v2: &[u32], bools: &[bool]) -> Wrapping<u32> {
-    let mut a: Wrapping<u32> = Wrapping(0);
-
-    for _ in 0..100 {
-        for i in 0..v1.len() {
-            if bools[i] {
-                a += v1[i];
-            } else {
-                a += v2[i];
-            }
-        }
-    }
-
-    a
+    println!("Checksum: {a}");
 }
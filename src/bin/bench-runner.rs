@@ -0,0 +1,64 @@
+use rust_perf_comp::{
+    kernel::find_kernel,
+    measure::{time_kernel, Measure},
+    perf_event::{count_kernel, Counters},
+    perf_stat::{PerfStatRecord, Phase},
+    *,
+};
+use std::{error::Error, process::ExitCode, time::Instant};
+
+pub fn main() -> ExitCode {
+    let args = parse_args();
+    exit_code(run(&args))
+}
+
+fn run(args: &AppArgs) -> Result<(), Box<dyn Error>> {
+    // clap only accepts the names of registered kernels
+    let kernel = find_kernel(&args.kernel).ok_or("unknown kernel")?;
+
+    match args.measure {
+        Measure::External => {
+            run_kernel(kernel, args)?;
+        }
+        Measure::Instant => {
+            let start = Instant::now();
+            let vecs = fill_vecs(args);
+            let setup_ns = start.elapsed().as_nanos() as f64;
+
+            let (timing, checksum) = time_kernel(kernel, &vecs, args.warmup, args.iterations);
+            args.write_metadata(vecs.bools(), checksum)?;
+            println!(
+                "Timed {} iterations, rejected {} as outliers",
+                args.iterations, timing.rejected
+            );
+
+            let setup = measure::Timing {
+                samples_ns: vec![setup_ns],
+                rejected: 0,
+            };
+            print_records(vec![setup.to_record()?], Phase::Setup)?;
+            print_records(vec![timing.to_record()?], Phase::Kernel)?;
+        }
+        Measure::PerfEvent => {
+            let counters = Counters::open()?;
+            let (setup, vecs) = counters.count(|| fill_vecs(args))?;
+            let (kernel_records, checksum) =
+                count_kernel(&counters, kernel, &vecs, args.warmup, args.iterations)?;
+            args.write_metadata(vecs.bools(), checksum)?;
+
+            print_records(counters.records(&[setup])?, Phase::Setup)?;
+            print_records(kernel_records, Phase::Kernel)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the records of the phase to stderr, in the same line-delimited JSON as `perf stat --json-output`
+fn print_records(records: Vec<PerfStatRecord>, phase: Phase) -> Result<(), Box<dyn Error>> {
+    for mut record in records {
+        record.phase = Some(phase);
+        eprintln!("{}", serde_json::to_string(&record)?);
+    }
+    Ok(())
+}
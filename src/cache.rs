@@ -0,0 +1,114 @@
+//! The cache hierarchy from sysfs, to sweep the working set of the kernels from L1 to DRAM
+use std::io;
+
+/// The caches of the first CPU, the others are assumed to have the same sizes
+const CPU0_CACHES: &str = "/sys/devices/system/cpu/cpu0/cache";
+
+/// The sizes are spaced by this factor, so every cache level has points on both sides of it
+const SIZE_STEP: f64 = std::f64::consts::SQRT_2;
+
+/// A data or unified cache level, the instruction caches don't hold the vectors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheLevel {
+    /// `L1d`, `L2` and so on, with the last level called `LLC`
+    pub name: String,
+    pub size_bytes: usize,
+}
+
+impl CacheLevel {
+    /// The name with the size, e.g. `L1d 48 KiB` or `LLC 32 MiB`
+    pub fn label(&self) -> String {
+        const MIB: usize = 1 << 20;
+        if self.size_bytes >= MIB && self.size_bytes.is_multiple_of(MIB) {
+            format!("{} {} MiB", self.name, self.size_bytes / MIB)
+        } else {
+            format!("{} {} KiB", self.name, self.size_bytes >> 10)
+        }
+    }
+
+    /// The number of elements whose vectors fill this cache
+    pub fn elements(&self, bytes_per_element: usize) -> usize {
+        self.size_bytes / bytes_per_element
+    }
+}
+
+/// The data and unified caches of cpu0 from the smallest to the largest level
+pub fn cache_levels() -> io::Result<Vec<CacheLevel>> {
+    let mut caches: Vec<(u32, usize)> = vec![];
+    for entry in std::fs::read_dir(CPU0_CACHES)? {
+        let path = entry?.path();
+        if !path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("index"))
+        {
+            continue;
+        }
+        let read = |file: &str| -> io::Result<String> {
+            Ok(std::fs::read_to_string(path.join(file))?.trim().to_string())
+        };
+        if read("type")? == "Instruction" {
+            continue;
+        }
+        let level = read("level")?;
+        let level = level.parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid cache level '{level}' in {path:?}: {e}"),
+            )
+        })?;
+        caches.push((level, parse_size(&read("size")?)?));
+    }
+    if caches.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no data caches in {CPU0_CACHES}"),
+        ));
+    }
+    caches.sort();
+    caches.dedup_by_key(|(level, _)| *level);
+
+    let last_level = caches.len() - 1;
+    Ok(caches
+        .into_iter()
+        .enumerate()
+        .map(|(i, (level, size_bytes))| CacheLevel {
+            name: match level {
+                1 => String::from("L1d"),
+                _ if i == last_level => String::from("LLC"),
+                _ => format!("L{level}"),
+            },
+            size_bytes,
+        })
+        .collect())
+}
+
+/// Parse a cache size as sysfs shows it, e.g. `48K` or `32M`
+fn parse_size(s: &str) -> io::Result<usize> {
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    digits.parse::<usize>().map(|d| d * unit).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid cache size '{s}': {e}"),
+        )
+    })
+}
+
+/// Numbers of elements from a quarter of the smallest cache to 4 times the largest, spaced by a factor of √2
+pub fn straddling_sizes(levels: &[CacheLevel], bytes_per_element: usize) -> Vec<usize> {
+    let (Some(smallest), Some(largest)) = (levels.first(), levels.last()) else {
+        return vec![];
+    };
+    let first = (smallest.elements(bytes_per_element) / 4).max(1) as f64;
+    let last = (largest.elements(bytes_per_element) * 4) as f64;
+    let mut sizes: Vec<usize> = std::iter::successors(Some(first), |n| Some(n * SIZE_STEP))
+        .take_while(|n| *n <= last)
+        .map(|n| n.round() as usize)
+        .collect();
+    sizes.dedup();
+    sizes
+}
@@ -0,0 +1,190 @@
+use crate::{
+    config::CompareArgs,
+    metrics::Metric,
+    perf_stat::{read_perf_stat_file, PerfStatRecord},
+    stats::{welch_t_test, Estimate, WelchTest},
+};
+use num_format::{Locale, ToFormattedString};
+use std::error::Error;
+
+/// A measurement of the baseline and the candidate, e.g. branching and branchless
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub name: String,
+    pub baseline: Estimate,
+    pub candidate: Estimate,
+}
+
+impl Comparison {
+    pub fn delta(&self) -> f64 {
+        self.candidate.mean - self.baseline.mean
+    }
+
+    pub fn delta_pct(&self) -> f64 {
+        self.delta() / self.baseline.mean * 100.
+    }
+
+    /// How many times larger the baseline is than the candidate, i.e. the speedup if the measurement is a cost
+    pub fn factor(&self) -> Estimate {
+        self.baseline.ratio(&self.candidate)
+    }
+
+    pub fn welch_t_test(&self) -> Option<WelchTest> {
+        welch_t_test(&self.baseline, &self.candidate)
+    }
+}
+
+/// Compare every event counted in both runs, followed by the derived metrics
+pub fn comparisons(
+    baseline: &[PerfStatRecord],
+    candidate: &[PerfStatRecord],
+    runs: u32,
+) -> Vec<Comparison> {
+    let mut comparisons = vec![];
+    for b in baseline {
+        let Some(c) = candidate
+            .iter()
+            .find(|c| c.event == b.event && c.phase == b.phase)
+        else {
+            continue;
+        };
+        if let (Some(baseline), Some(candidate)) = (
+            Estimate::from_record(b, runs),
+            Estimate::from_record(c, runs),
+        ) {
+            comparisons.push(Comparison {
+                name: with_phase(b.event.to_string(), b),
+                baseline,
+                candidate,
+            });
+        }
+    }
+
+    // perf doesn't know the number of elements
+    for metric in Metric::ALL.into_iter().filter(|m| !m.needs_elements()) {
+        let baseline = derived_metric(baseline, runs, metric);
+        let candidate = derived_metric(candidate, runs, metric);
+        for (label, b) in baseline {
+            if let Some((_, c)) = candidate.iter().find(|(l, _)| *l == label) {
+                comparisons.push(Comparison {
+                    name: format!("{label} {}", metric.label()),
+                    baseline: b,
+                    candidate: *c,
+                });
+            }
+        }
+    }
+    comparisons
+}
+
+/// The metric of every PMU that counted its inputs, labelled by the PMU and phase
+fn derived_metric(
+    records: &[PerfStatRecord],
+    runs: u32,
+    metric: Metric,
+) -> Vec<(String, Estimate)> {
+    metric
+        .per_pmu(records, runs, None)
+        .into_iter()
+        .map(|m| {
+            let label = match &m.numerator.event.pmu {
+                Some(pmu) => pmu.clone(),
+                None => String::from("all"),
+            };
+            (with_phase(label, m.numerator), m.value)
+        })
+        .collect()
+}
+
+/// Mark the name as a measurement of the setup if it is one, the kernel is what's compared by default
+fn with_phase(name: String, record: &PerfStatRecord) -> String {
+    if record.is_setup() {
+        format!("{name} (setup)")
+    } else {
+        name
+    }
+}
+
+pub fn compare(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    let baseline = read_perf_stat_file(&args.baseline)?;
+    let candidate = read_perf_stat_file(&args.candidate)?;
+    let comparisons = comparisons(&baseline, &candidate, args.runs);
+    if comparisons.is_empty() {
+        return Err(format!(
+            "{:?} and {:?} have no counted events in common",
+            args.baseline, args.candidate
+        )
+        .into());
+    }
+
+    let name_width = comparisons
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    println!(
+        "{:<name_width$} | {:>22} | {:>22} | {:>16} | {:>9} | {:>16} | significance (Welch's t-test, alpha={})",
+        "Event",
+        format!("{} ±stddev%", args.baseline_name),
+        format!("{} ±stddev%", args.candidate_name),
+        "Δ",
+        "Δ%",
+        "factor",
+        args.alpha,
+    );
+    println!("{}", "-".repeat(name_width + 140));
+    for c in &comparisons {
+        let factor = c.factor();
+        let factor = if factor.mean.is_finite() {
+            format!("{:.3}x ±{:.3}", factor.mean, factor.sem)
+        } else {
+            String::from("n/a")
+        };
+        println!(
+            "{:<name_width$} | {:>14} ±{:>5.2}% | {:>14} ±{:>5.2}% | {:>16} | {:>8.2}% | {:>16} | {}",
+            c.name,
+            format_value(c.baseline.mean),
+            c.baseline.rel_stddev_pct(),
+            format_value(c.candidate.mean),
+            c.candidate.rel_stddev_pct(),
+            format_value(c.delta()),
+            c.delta_pct(),
+            factor,
+            verdict(c.welch_t_test(), args.alpha),
+        );
+    }
+
+    if let Some(duration) = comparisons.iter().find(|c| c.name == "duration_time") {
+        let factor = duration.factor();
+        let (factor, faster_or_slower) = if factor.mean >= 1. {
+            (factor, "faster")
+        } else {
+            (duration.candidate.ratio(&duration.baseline), "slower")
+        };
+        println!(
+            "\n{} is {:.2}x {faster_or_slower} ± {:.2} than {}",
+            args.candidate_name, factor.mean, factor.sem, args.baseline_name
+        );
+    }
+
+    Ok(())
+}
+
+fn verdict(test: Option<WelchTest>, alpha: f64) -> String {
+    match test {
+        Some(WelchTest { p, .. }) if p < alpha => format!("significant (p={p:.2e})"),
+        Some(WelchTest { p, .. }) => format!("not significant (p={p:.2})"),
+        None => String::from("n/a (no variance, run perf with --repeat)"),
+    }
+}
+
+/// Counts with thousands separators, small values such as ratios with decimals
+fn format_value(v: f64) -> String {
+    if v.abs() >= 1000. {
+        let rounded = v.round() as i64;
+        rounded.to_formatted_string(&Locale::en)
+    } else {
+        format!("{v:.3}")
+    }
+}
@@ -1,3 +1,9 @@
+use crate::{
+    element::ElementType,
+    metrics::Metric,
+    pattern::{parse_ratio, Pattern},
+    perf_stat::Event,
+};
 use clap::*;
 use std::path::PathBuf;
 
@@ -22,8 +28,10 @@ pub enum Command {
         in_branchless_json: PathBuf,
     },
     LineOverX {
+        /// The values of the parameter the files were measured at, e.g. `branch50.json` for 50 or `branchu8.json`
+        /// for u8 with `--over element`
         #[arg(required = true)]
-        x_vals: Vec<u64>,
+        x_vals: Vec<String>,
         #[arg(long)]
         json_dir: PathBuf,
         #[arg(long)]
@@ -32,9 +40,167 @@ pub enum Command {
         branchless_prefix: String,
         #[arg(long)]
         save_to: PathBuf,
+        #[arg(long, required_unless_present_any = ["events", "metrics", "list_events"])]
+        plot_type: Option<PlotType>,
+        /// Plot this event instead of the `--plot-type`, can be given more than once for a panel per event
+        ///
+        /// Without a PMU the event is plotted for every PMU that counted it, e.g. `instructions` for both
+        /// `cpu_core/instructions/` and `cpu_atom/instructions/` on a hybrid CPU, see `--list-events`.
+        #[arg(long = "event", value_name = "EVENT", conflicts_with = "plot_type")]
+        events: Vec<Event>,
+        /// Plot this metric of every PMU like an `--event`, can be given more than once for a panel per metric:
+        /// ipc, cpi, branch-miss-pct, mpki (branch misses per 1000 instructions), branches-per-element,
+        /// instructions-per-element or cycles-per-element
+        ///
+        /// The per-element metrics take the number of elements from the run manifests, `<PREFIX><X>.meta.json`.
+        #[arg(long = "metric", value_name = "METRIC", conflicts_with = "plot_type")]
+        metrics: Vec<Metric>,
+        /// List the events in the JSON files and exit
         #[arg(long)]
-        plot_type: PlotType,
+        list_events: bool,
+        /// The parameter on the x-axis
+        #[arg(long, default_value = "ratio")]
+        over: SweepParam,
+        /// Scale of the x-axis, `auto` is log scale if the parameter's values span two decades or more
+        #[arg(long, default_value = "auto")]
+        x_scale: XScale,
+        /// What to divide the values by, per element and per iteration take N from the run manifests
+        #[arg(long, default_value = "none")]
+        normalize: Normalize,
+        #[command(flatten)]
+        coverage: Coverage,
     },
+    /// Run `perf stat` on the benchmark kernels for each value of a parameter and plot them against the baseline
+    Sweep(SweepArgs),
+    /// Compare the perf stat results of two programs, e.g. branching vs. branchless
+    Compare(CompareArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompareArgs {
+    /// perf stat output of the baseline, e.g. the branching example
+    pub baseline: PathBuf,
+    /// perf stat output to compare against the baseline, e.g. the branchless example
+    pub candidate: PathBuf,
+    /// The `--repeat` count perf stat was run with, perf doesn't record it but the errors depend on it
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub runs: u32,
+    #[arg(long, default_value = "branching")]
+    pub baseline_name: String,
+    #[arg(long, default_value = "branchless")]
+    pub candidate_name: String,
+    /// Significance level for the difference between the two means
+    #[arg(long, default_value_t = 0.05)]
+    pub alpha: f64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SweepArgs {
+    /// Values of the swept parameter, e.g. ratios in percent (0-100, e.g. 0.1 or 99.9) or in ppm (e.g. 250ppm),
+    /// the number of elements with `--over n` or element types with `--over element`
+    #[arg(required_unless_present = "cache_sizes")]
+    pub values: Vec<String>,
+    /// The parameter to sweep, the others are fixed to their options
+    #[arg(long, default_value = "ratio")]
+    pub over: SweepParam,
+    /// Sweep N from a quarter of the L1d cache to 4 times the last level cache of cpu0 and mark the cache sizes
+    /// in the plots, instead of the given values
+    #[arg(long, conflicts_with_all = ["values", "over"])]
+    pub cache_sizes: bool,
+    /// Ratio of true values when sweeping another parameter, in percent or in ppm
+    #[arg(long, default_value = "50", value_parser = parse_ratio)]
+    pub ratio: f64,
+    /// Number of elements in the vectors
+    #[arg(long = "N", default_value_t = 10_000_000, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub n: usize,
+    /// Seed for the random number generator
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Number of times the kernel is measured per value
+    #[arg(long, default_value_t = 5)]
+    pub repeat: u32,
+    /// How to measure the kernels
+    #[arg(long, default_value = "perf")]
+    pub backend: Backend,
+    /// The core PMU to count the CPU events on with the `perf` backend, e.g. `cpu_core` or `armv8_pmuv3_0` as
+    /// listed in /sys/bus/event_source/devices, or `all` for every core PMU
+    #[arg(long, value_name = "PMU", default_value = "all")]
+    pub events: EventSet,
+    /// Spread the true values evenly instead of randomly, the same as `--pattern deterministic`
+    #[arg(long, conflicts_with = "pattern")]
+    pub no_rnd_cmp: bool,
+    /// The pattern of the bools, e.g. `runs:8`, `periodic:TTFTF`, `markov:0.1` or `noisy:5`, see `bench-runner --help`
+    #[arg(long, default_value = "random")]
+    pub pattern: Pattern,
+    /// Let perf count the whole runner process instead of only the kernel, for perf versions without `--control`
+    #[arg(long)]
+    pub no_phase_markers: bool,
+    /// Also measure the setup of the vectors as separate records, with perf this is a separate perf stat run.
+    /// The in-process backends always measure it
+    #[arg(long, conflicts_with = "no_phase_markers")]
+    pub measure_setup: bool,
+    #[arg(long, default_value = "target/release/bench-runner")]
+    pub runner: PathBuf,
+    /// The kernel the others are compared against
+    #[arg(long, default_value = "branch")]
+    pub baseline: String,
+    /// Kernels to compare against the baseline, `all` for every registered kernel
+    #[arg(long = "kernel", default_value = "branchless")]
+    pub kernels: Vec<String>,
+    /// Element type of the vectors: u8, u16, u32, u64, f32 or f64
+    #[arg(long, default_value = "u32")]
+    pub element: ElementType,
+    /// Directory to write the plots to, in a sub-directory per kernel if there's more than one
+    #[arg(long)]
+    pub save_to: PathBuf,
+    /// Scale of the x-axis, `auto` is log scale if the swept values span two decades or more
+    #[arg(long, default_value = "auto")]
+    pub x_scale: XScale,
+    /// Also store the perf stat records as `<KERNEL><RATIO>.json`, e.g. `branch50.json` for `line-over-x`,
+    /// along with the manifest of the run with the achieved ratio and checksum as `<KERNEL><RATIO>.meta.json`
+    #[arg(long)]
+    pub json_dir: Option<PathBuf>,
+    /// What to divide the values in the plots by
+    #[arg(long, default_value = "none")]
+    pub normalize: Normalize,
+    #[command(flatten)]
+    pub coverage: Coverage,
+}
+
+/// What the values in the plots are divided by, to compare runs with a different N or from different machines
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Normalize {
+    /// The values of a whole run of the kernel
+    None,
+    /// Per element of the vectors, i.e. divided by N, the durations in ns
+    PerElement,
+    /// Per iteration of the inner loop of the kernels, i.e. divided by N * 100 as they pass over the vectors 100
+    /// times, the durations in ns
+    PerIteration,
+    /// Per second the kernel ran, e.g. instructions per second, the durations aren't divided
+    PerSecond,
+}
+
+/// How the plots treat counters that perf only counted for part of the time and extrapolated to the whole run
+#[derive(Debug, Clone, Copy, clap::Args)]
+pub struct Coverage {
+    /// Counters that ran for less than this percentage of the time have low coverage, e.g. the `cpu_atom` events on a
+    /// hybrid CPU when the kernel ran on the P-cores, their counts are extrapolated from a short sample
+    #[arg(long, default_value_t = 10., value_name = "PCT")]
+    pub min_pcnt_running: f32,
+    /// What the plots do with the counters with low coverage
+    #[arg(long, default_value = "de-emphasize")]
+    pub low_coverage: LowCoverage,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LowCoverage {
+    /// Plot them like the other counters and include them in the totals over the PMUs
+    Keep,
+    /// Draw their lines faded and leave them out of the totals over the PMUs
+    DeEmphasize,
+    /// Leave them out of the plots
+    Exclude,
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
@@ -43,3 +209,60 @@ pub enum PlotType {
     TimeBranchMisses,
     Merged,
 }
+
+/// A parameter of the runs that a sweep varies and plots on the x-axis
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SweepParam {
+    /// The ratio of true values
+    Ratio,
+    /// The number of elements in the vectors
+    #[value(alias = "N")]
+    N,
+    /// The seed of the random number generator
+    Seed,
+    /// The number of times the kernel is measured
+    Repeat,
+    /// The element type of the vectors, e.g. `u8` or `f64`
+    Element,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum XScale {
+    /// Log scale if the parameter's values span two decades or more
+    Auto,
+    Linear,
+    Log,
+}
+
+/// The core PMUs to count the CPU events on with the `perf` backend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventSet {
+    /// Every core PMU, e.g. both `cpu_core` and `cpu_atom` on a hybrid Intel CPU
+    All,
+    /// Only the PMU with this name, e.g. `cpu_core`, `armv8_pmuv3_0` or `cpu`
+    Pmu(String),
+}
+
+impl std::str::FromStr for EventSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            // `both` is what this was called when only hybrid Intel CPUs were supported
+            "all" | "both" => Ok(EventSet::All),
+            "" => Err(String::from("expected `all` or the name of a core PMU")),
+            pmu if pmu.contains(['/', ',']) => Err(format!("invalid PMU name '{pmu}'")),
+            pmu => Ok(EventSet::Pmu(pmu.to_string())),
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Count the events with `perf stat`
+    Perf,
+    /// Time the kernels in the runner with `std::time::Instant`, only measures the duration
+    Instant,
+    /// Count the events on every PMU in the runner with `perf_event_open`, excluding the setup of the vectors
+    PerfEvent,
+}
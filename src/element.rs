@@ -0,0 +1,296 @@
+//! The element types of the vectors the kernels select from, e.g. `u32` or `f64`
+//!
+//! Whether the compiler emits a branch, a `cmov` or a blend, and whether the loop is vectorized, depends on the width
+//! and kind of the elements, so the kernels are written once over [`Element`] and run on the type from the command line.
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    ops::{BitAnd, BitOr, Not},
+    str::FromStr,
+};
+
+/// The element type selected on the command line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElementType {
+    U8,
+    U16,
+    #[default]
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl ElementType {
+    pub const ALL: [ElementType; 6] = [
+        ElementType::U8,
+        ElementType::U16,
+        ElementType::U32,
+        ElementType::U64,
+        ElementType::F32,
+        ElementType::F64,
+    ];
+
+    pub fn size(&self) -> usize {
+        match self {
+            ElementType::U8 => 1,
+            ElementType::U16 => 2,
+            ElementType::U32 | ElementType::F32 => 4,
+            ElementType::U64 | ElementType::F64 => 8,
+        }
+    }
+
+    /// The bytes of the vectors per element, a value in `v1` and `v2` and the bool
+    pub fn bytes_per_element(&self) -> usize {
+        2 * self.size() + std::mem::size_of::<bool>()
+    }
+}
+
+impl FromStr for ElementType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ElementType::ALL
+            .into_iter()
+            .find(|t| t.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown element type '{s}', expected one of: {}",
+                    ElementType::ALL.map(|t| t.to_string()).join(", ")
+                )
+            })
+    }
+}
+
+impl Display for ElementType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ElementType::U8 => "u8",
+            ElementType::U16 => "u16",
+            ElementType::U32 => "u32",
+            ElementType::U64 => "u64",
+            ElementType::F32 => "f32",
+            ElementType::F64 => "f64",
+        })
+    }
+}
+
+/// What a kernel sums up to, the same for every kernel given the same vectors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Checksum {
+    /// The wrapping sum of integers
+    Int(u64),
+    /// The sum of floats, which depends on the order of the additions once it's too large to be exact
+    Float(f64),
+}
+
+impl Checksum {
+    /// If the sum of elements of the type is exact, so every kernel has to arrive at the same one
+    ///
+    /// The elements are whole numbers, so a float sum is exact until it passes the largest integer the type holds
+    /// exactly, from there it's rounded differently depending on the order of the additions.
+    pub fn is_exact(&self, element: ElementType) -> bool {
+        let mantissa_digits = match element {
+            ElementType::F32 => f32::MANTISSA_DIGITS,
+            _ => f64::MANTISSA_DIGITS,
+        };
+        match self {
+            Checksum::Int(_) => true,
+            Checksum::Float(sum) => *sum <= (1u64 << mantissa_digits) as f64,
+        }
+    }
+}
+
+impl Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Checksum::Int(sum) => write!(f, "{sum}"),
+            Checksum::Float(sum) => write!(f, "{sum}"),
+        }
+    }
+}
+
+/// A type the kernels can select and sum
+pub trait Element: Copy + Send + Sync + 'static {
+    const ZERO: Self;
+    /// The unsigned integer of the same width, the mask kernels blend the bits
+    type Bits: Copy
+        + BitAnd<Output = Self::Bits>
+        + BitOr<Output = Self::Bits>
+        + Not<Output = Self::Bits>;
+
+    /// Wrapping for integers, so the sum doesn't depend on the order of the additions
+    fn add(self, other: Self) -> Self;
+    fn to_bits(self) -> Self::Bits;
+    fn from_bits(bits: Self::Bits) -> Self;
+    /// All ones if `b` is true and all zeros if it's false
+    fn mask(b: bool) -> Self::Bits;
+    fn random(rng: &mut ChaCha8Rng) -> Self;
+    fn checksum(self) -> Checksum;
+    fn into_any(vecs: Vecs<Self>) -> AnyVecs;
+}
+
+macro_rules! impl_int_element {
+    ($t:ty, $variant:ident) => {
+        impl Element for $t {
+            const ZERO: Self = 0;
+            type Bits = $t;
+
+            #[inline(always)]
+            fn add(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+
+            #[inline(always)]
+            fn to_bits(self) -> Self::Bits {
+                self
+            }
+
+            #[inline(always)]
+            fn from_bits(bits: Self::Bits) -> Self {
+                bits
+            }
+
+            #[inline(always)]
+            fn mask(b: bool) -> Self::Bits {
+                (b as $t).wrapping_neg()
+            }
+
+            fn random(rng: &mut ChaCha8Rng) -> Self {
+                rng.gen()
+            }
+
+            fn checksum(self) -> Checksum {
+                Checksum::Int(self as u64)
+            }
+
+            fn into_any(vecs: Vecs<Self>) -> AnyVecs {
+                AnyVecs::$variant(vecs)
+            }
+        }
+    };
+}
+
+macro_rules! impl_float_element {
+    ($t:ty, $bits:ty, $variant:ident) => {
+        impl Element for $t {
+            const ZERO: Self = 0.;
+            type Bits = $bits;
+
+            #[inline(always)]
+            fn add(self, other: Self) -> Self {
+                self + other
+            }
+
+            #[inline(always)]
+            fn to_bits(self) -> Self::Bits {
+                self.to_bits()
+            }
+
+            #[inline(always)]
+            fn from_bits(bits: Self::Bits) -> Self {
+                <$t>::from_bits(bits)
+            }
+
+            #[inline(always)]
+            fn mask(b: bool) -> Self::Bits {
+                (b as $bits).wrapping_neg()
+            }
+
+            /// Whole numbers below 256, so the sums stay exact for as long as the mantissa allows
+            fn random(rng: &mut ChaCha8Rng) -> Self {
+                rng.gen::<u8>() as $t
+            }
+
+            fn checksum(self) -> Checksum {
+                Checksum::Float(self as f64)
+            }
+
+            fn into_any(vecs: Vecs<Self>) -> AnyVecs {
+                AnyVecs::$variant(vecs)
+            }
+        }
+    };
+}
+
+impl_int_element!(u8, U8);
+impl_int_element!(u16, U16);
+impl_int_element!(u32, U32);
+impl_int_element!(u64, U64);
+impl_float_element!(f32, u32, F32);
+impl_float_element!(f64, u64, F64);
+
+/// The vectors a kernel runs on, it sums `v1[j]` where `bools[j]` is true and `v2[j]` where it's false
+pub struct Vecs<T> {
+    pub v1: Vec<T>,
+    pub v2: Vec<T>,
+    pub bools: Vec<bool>,
+}
+
+/// [`Vecs`] of the element type selected on the command line
+pub enum AnyVecs {
+    U8(Vecs<u8>),
+    U16(Vecs<u16>),
+    U32(Vecs<u32>),
+    U64(Vecs<u64>),
+    F32(Vecs<f32>),
+    F64(Vecs<f64>),
+}
+
+/// Evaluate `$body` with `$v` bound to the typed [`Vecs`] inside the [`AnyVecs`]
+macro_rules! with_vecs {
+    ($any:expr, $v:ident => $body:expr) => {
+        match $any {
+            $crate::element::AnyVecs::U8($v) => $body,
+            $crate::element::AnyVecs::U16($v) => $body,
+            $crate::element::AnyVecs::U32($v) => $body,
+            $crate::element::AnyVecs::U64($v) => $body,
+            $crate::element::AnyVecs::F32($v) => $body,
+            $crate::element::AnyVecs::F64($v) => $body,
+        }
+    };
+}
+pub(crate) use with_vecs;
+
+/// Evaluate `$body` with the type alias `$t` set to the Rust type of the [`ElementType`]
+macro_rules! with_element_type {
+    ($element:expr, $t:ident => $body:expr) => {
+        match $element {
+            $crate::element::ElementType::U8 => {
+                type $t = u8;
+                $body
+            }
+            $crate::element::ElementType::U16 => {
+                type $t = u16;
+                $body
+            }
+            $crate::element::ElementType::U32 => {
+                type $t = u32;
+                $body
+            }
+            $crate::element::ElementType::U64 => {
+                type $t = u64;
+                $body
+            }
+            $crate::element::ElementType::F32 => {
+                type $t = f32;
+                $body
+            }
+            $crate::element::ElementType::F64 => {
+                type $t = f64;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_element_type;
+
+impl AnyVecs {
+    pub fn bools(&self) -> &[bool] {
+        with_vecs!(self, v => &v.bools)
+    }
+}
@@ -0,0 +1,244 @@
+use crate::element::{with_vecs, AnyVecs, Checksum, Element};
+use std::hint::{black_box, select_unpredictable};
+
+/// How many times every kernel passes over the vectors, so a run of a kernel makes `N * PASSES` iterations
+pub const PASSES: usize = 100;
+
+/// A benchmark kernel that picks from `v1` or `v2` depending on `bools` and accumulates the result
+pub trait Kernel: Sync {
+    /// The name used to select the kernel, e.g. `--kernel branchless`
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Whether the kernel adds the selected values one after the other from the first to the last, so a float sum
+    /// is rounded exactly like the other kernels' that do
+    fn adds_in_order(&self) -> bool;
+    fn run(&self, vecs: &AnyVecs) -> Checksum;
+}
+
+/// A kernel written once for every [`Element`] type, it's a [`Kernel`] on the vectors of any of them
+pub trait GenericKernel: Sync {
+    const NAME: &'static str;
+    const DESCRIPTION: &'static str;
+    const ADDS_IN_ORDER: bool = true;
+
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T;
+}
+
+impl<K: GenericKernel> Kernel for K {
+    fn name(&self) -> &'static str {
+        K::NAME
+    }
+
+    fn description(&self) -> &'static str {
+        K::DESCRIPTION
+    }
+
+    fn adds_in_order(&self) -> bool {
+        K::ADDS_IN_ORDER
+    }
+
+    fn run(&self, vecs: &AnyVecs) -> Checksum {
+        with_vecs!(vecs, v => K::sum(&v.v1, &v.v2, &v.bools).checksum())
+    }
+}
+
+/// All registered kernels, new kernels only need to be added here to be selectable
+pub static KERNELS: &[&dyn Kernel] = &[
+    &Branch,
+    &Branchless,
+    &Mask,
+    &ThenSome,
+    &SelectUnpredictable,
+    &BlackBoxIf,
+    &LaneSelect,
+];
+
+/// Look up a registered kernel by its name
+pub fn find_kernel(name: &str) -> Option<&'static dyn Kernel> {
+    KERNELS.iter().copied().find(|k| k.name() == name)
+}
+
+/// The names of all registered kernels
+pub fn kernel_names() -> Vec<&'static str> {
+    KERNELS.iter().map(|k| k.name()).collect()
+}
+
+pub struct Branch;
+
+impl GenericKernel for Branch {
+    const NAME: &'static str = "branch";
+    const DESCRIPTION: &'static str = "if/else on the bool";
+
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            for j in 0..v1.len() {
+                if bools[j] {
+                    a = a.add(v1[j]);
+                } else {
+                    a = a.add(v2[j]);
+                }
+            }
+        }
+
+        a
+    }
+}
+
+pub struct Branchless;
+
+impl GenericKernel for Branchless {
+    const NAME: &'static str = "branchless";
+    const DESCRIPTION: &'static str = "index a [v2, v1] array with the bool";
+
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            for j in 0..v1.len() {
+                // `true as usize` is 1, so v1 goes second to be picked where the bool is true like in the other kernels
+                let vals = [v2[j], v1[j]];
+                a = a.add(vals[bools[j] as usize]);
+            }
+        }
+
+        a
+    }
+}
+
+pub struct Mask;
+
+impl GenericKernel for Mask {
+    const NAME: &'static str = "mask";
+    const DESCRIPTION: &'static str =
+        "blend v1 and v2 with an all-ones/all-zeros mask made from the bool";
+
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            for j in 0..v1.len() {
+                let mask = T::mask(bools[j]);
+                a = a.add(T::from_bits(
+                    (v1[j].to_bits() & mask) | (v2[j].to_bits() & !mask),
+                ));
+            }
+        }
+
+        a
+    }
+}
+
+pub struct ThenSome;
+
+impl GenericKernel for ThenSome {
+    const NAME: &'static str = "then-some";
+    const DESCRIPTION: &'static str = "bool::then_some(v1).unwrap_or(v2)";
+
+    // The idiom is what's being measured, so don't let clippy rewrite it into the `if` of `Branch`
+    #[allow(clippy::obfuscated_if_else)]
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            for j in 0..v1.len() {
+                a = a.add(bools[j].then_some(v1[j]).unwrap_or(v2[j]));
+            }
+        }
+
+        a
+    }
+}
+
+pub struct SelectUnpredictable;
+
+impl GenericKernel for SelectUnpredictable {
+    const NAME: &'static str = "select-unpredictable";
+    const DESCRIPTION: &'static str = "std::hint::select_unpredictable on the bool";
+
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            for j in 0..v1.len() {
+                a = a.add(select_unpredictable(bools[j], v1[j], v2[j]));
+            }
+        }
+
+        a
+    }
+}
+
+pub struct BlackBoxIf;
+
+impl GenericKernel for BlackBoxIf {
+    const NAME: &'static str = "black-box-if";
+    const DESCRIPTION: &'static str =
+        "if/else on a black_box'ed bool, so the compiler can't turn it into a select";
+
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            for j in 0..v1.len() {
+                if black_box(bools[j]) {
+                    a = a.add(v1[j]);
+                } else {
+                    a = a.add(v2[j]);
+                }
+            }
+        }
+
+        a
+    }
+}
+
+/// Number of lanes in [`LaneSelect`], 8 `u32` or `f32` fill a 256-bit vector register
+const LANES: usize = 8;
+
+pub struct LaneSelect;
+
+impl GenericKernel for LaneSelect {
+    const NAME: &'static str = "lane-select";
+    const DESCRIPTION: &'static str = "mask select on chunks of 8 lanes with a lane-wise accumulator, written to auto-vectorize into blends";
+    // Every lane sums its own share of the values
+    const ADDS_IN_ORDER: bool = false;
+
+    #[inline(never)]
+    fn sum<T: Element>(v1: &[T], v2: &[T], bools: &[bool]) -> T {
+        let mut a = T::ZERO;
+
+        for _ in 0..PASSES {
+            let mut lanes = [T::ZERO; LANES];
+            let chunks = v1
+                .chunks_exact(LANES)
+                .zip(v2.chunks_exact(LANES))
+                .zip(bools.chunks_exact(LANES));
+            for ((c1, c2), cb) in chunks {
+                for l in 0..LANES {
+                    let mask = T::mask(cb[l]);
+                    lanes[l] = lanes[l].add(T::from_bits(
+                        (c1[l].to_bits() & mask) | (c2[l].to_bits() & !mask),
+                    ));
+                }
+            }
+            for lane in lanes {
+                a = a.add(lane);
+            }
+
+            let tail = v1.len() - v1.len() % LANES;
+            for j in tail..v1.len() {
+                a = a.add(if bools[j] { v1[j] } else { v2[j] });
+            }
+        }
+
+        a
+    }
+}
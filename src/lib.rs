@@ -1,33 +1,152 @@
 #![allow(non_snake_case)]
+use clap::{builder::PossibleValuesParser, ArgAction, Parser};
+use element::{with_element_type, AnyVecs, Checksum, Element, ElementType, Vecs};
+use kernel::Kernel;
+use measure::Measure;
+use metadata::RunMetadata;
 use num_format::{Locale, ToFormattedString};
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha8Rng;
+use pattern::Pattern;
+use perf_stat::Phase;
+use phase::{run_phase, PerfControl};
+use rand::SeedableRng;
 pub use std::num::Wrapping;
+use std::{
+    error::Error,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+    time::Duration,
+};
 
+pub mod cache;
+pub mod compare;
 pub mod config;
+pub mod element;
+pub mod kernel;
+pub mod measure;
+pub mod metadata;
+pub mod metrics;
+pub mod pattern;
+pub mod perf_event;
+pub mod perf_stat;
+pub mod phase;
 pub mod plot_perf_stats;
+pub mod stats;
+pub mod sweep;
 
-const HELP: &str = "\
-Rust performance comparitor
-
-USAGE:
-  rust-perf-comp --seed <SEED> --N <N> --ratio <RATIO> [--no-rnd-cmp]
-
-FLAGS:
-  -h, --help            Prints help information
-
-OPTIONS:
-  --seed <SEED>         Seed for the random number generator
-  --N <N>               Number of elements in the vectors
-  --ratio <RATIO>       Ratio of true values (0-100 %)
-  --no-rnd-cmp          Disable random comparison generation and use <RATIO> for modulo comparison
-";
-
+/// Fill the vectors and run a benchmark kernel on them, e.g. under `perf stat`
+#[derive(Debug, Parser)]
 pub struct AppArgs {
+    /// Seed for the random number generator
+    #[arg(long, default_value_t = 0)]
     pub seed: u64,
+    /// Number of elements in the vectors
+    #[arg(long = "N", default_value_t = 10_000_000, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
     pub N: usize,
-    pub ratio: u16,
+    /// Ratio of true values in percent (0-100, e.g. 0.1) or in ppm with a `ppm` suffix (e.g. 250ppm)
+    #[arg(long, default_value = "100", value_parser = pattern::parse_ratio)]
+    pub ratio: f64,
+    /// Spread the true values evenly instead of randomly, the same as `--pattern deterministic`
+    #[arg(long = "no-rnd-cmp", action = ArgAction::SetFalse, conflicts_with = "pattern")]
     pub rnd_cmp: bool,
+    /// The pattern of the bools: random, deterministic, runs:<K> (runs of K equal bools), periodic:<TTFTF>
+    /// (repeats the Ts and Fs), markov:<SWITCH PROBABILITY> (0-1), sorted, reverse-sorted or noisy:<NOISE %>
+    /// (reverse-sorted with % flipped)
+    #[arg(long, default_value = "random")]
+    pub pattern: Pattern,
+    /// Kernel to run, see --list-kernels
+    #[arg(long, default_value = "branch", value_parser = PossibleValuesParser::new(kernel::kernel_names()))]
+    pub kernel: String,
+    /// Element type of the vectors: u8, u16, u32, u64, f32 or f64
+    #[arg(long, default_value = "u32")]
+    pub element: ElementType,
+    /// How the kernel is measured: `external` (e.g. perf stat), `instant` to time it in-process or `perf-event` to
+    /// count events around the kernel only. The last two output perf stat JSON records on stderr
+    #[arg(long, default_value = "external")]
+    pub measure: Measure,
+    /// Unmeasured runs of the kernel with `instant` and `perf-event`
+    #[arg(long, default_value_t = 1)]
+    pub warmup: u32,
+    /// Measured runs of the kernel with `instant` and `perf-event`
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+    /// Enable perf only around the --phase when run by `perf stat --delay=-1 --control=fifo:<CTL>,<ACK>`
+    #[arg(long, value_name = "CTL", requires = "ack_fifo")]
+    pub control_fifo: Option<PathBuf>,
+    /// The ack FIFO of `perf stat --control`
+    #[arg(long, value_name = "ACK", requires = "control_fifo")]
+    pub ack_fifo: Option<PathBuf>,
+    /// The phase perf is enabled around, `setup` or `kernel`
+    #[arg(long, default_value = "kernel")]
+    pub phase: Phase,
+    /// Append the wall-clock time of the --phase in ns to <FILE>, as perf's `duration_time` is of the whole runner
+    /// even while its counters are disabled
+    #[arg(long, value_name = "FILE", requires = "control_fifo")]
+    pub phase_durations: Option<PathBuf>,
+    /// Write a manifest of the run to <FILE> as JSON: the parameters, the achieved ratio of true values, the
+    /// checksum of the kernel and the build and machine it ran on
+    #[arg(long, alias = "manifest", value_name = "FILE")]
+    pub metadata: Option<PathBuf>,
+    /// Don't print the parameters of the run
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// List the kernels and exit
+    #[arg(long)]
+    pub list_kernels: bool,
+}
+
+impl AppArgs {
+    /// The control of the perf stat running the runner, if it was given the FIFOs
+    pub fn perf_control(&self) -> io::Result<Option<PerfControl>> {
+        match (&self.control_fifo, &self.ack_fifo) {
+            (Some(ctl), Some(ack)) => PerfControl::open(ctl, ack).map(Some).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to open the perf control FIFOs {ctl:?} and {ack:?}: {e}"),
+                )
+            }),
+            (None, None) => Ok(None),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--control-fifo and --ack-fifo have to be given together",
+            )),
+        }
+    }
+
+    /// Append the duration of the measured phase to the `--phase-durations` file if one was given
+    pub fn append_phase_duration(&self, duration: Duration) -> io::Result<()> {
+        let Some(path) = &self.phase_durations else {
+            return Ok(());
+        };
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", duration.as_nanos()))
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to write the duration of the phase to {path:?}: {e}"),
+                )
+            })
+    }
+
+    /// Write the metadata of the run with the generated bools and the checksum of the kernel if a `--metadata` file
+    /// was given
+    pub fn write_metadata(&self, bools: &[bool], checksum: Option<Checksum>) -> io::Result<()> {
+        match &self.metadata {
+            Some(path) => RunMetadata::new(self, bools, checksum)
+                .write(path)
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("Failed to write the metadata to {path:?}: {e}"),
+                    )
+                }),
+            None => Ok(()),
+        }
+    }
 }
 
 impl std::fmt::Display for AppArgs {
@@ -35,56 +154,42 @@ impl std::fmt::Display for AppArgs {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Requested ratio: {}% | N: {} | Seed={} ",
+            "Requested ratio: {}% | N: {} | Seed={} | Element: {} ",
             self.ratio,
             self.N.to_formatted_string(&Locale::en),
             self.seed,
+            self.element,
         )
     }
 }
 
+/// Parse the arguments of the runner, exits with the usage on invalid arguments
 #[inline(always)]
 pub fn parse_args() -> AppArgs {
-    let mut pargs = pico_args::Arguments::from_env();
-
-    // Help has a higher priority and should be handled separately.
-    if pargs.contains(["-h", "--help"]) {
-        print!("{HELP}");
+    let mut args = AppArgs::parse();
+    if args.list_kernels {
+        for k in kernel::KERNELS {
+            println!("{:<12} {}", k.name(), k.description());
+        }
         std::process::exit(0);
     }
+    if !args.rnd_cmp {
+        args.pattern = Pattern::Deterministic;
+    }
 
-    let args = AppArgs {
-        seed: pargs.value_from_str("--seed").unwrap(),
-        N: pargs.value_from_str("--N").unwrap(),
-        ratio: pargs.value_from_str("--ratio").unwrap(),
-        rnd_cmp: !pargs.contains("--no-rnd-cmp"),
-    };
-    println!("{args}");
+    if !args.quiet {
+        println!("{args}");
+    }
     args
 }
 
-/// Convert the ratio specified in percentage, to a u8 value for either random comparison or modulo comparison
-#[inline(always)]
-fn arg_ratio_to_u16(args: &AppArgs) -> u16 {
-    if args.rnd_cmp {
-        if args.ratio == 100 {
-            256
-        } else {
-            (u8::MAX as f32 / 100.0 * args.ratio as f32) as u16
-        }
-    } else {
-        // Convert the percentage to a value for use in modulo operation
-        // It doesn't fit very well with the 0-100% range, but it's good enough for this purpose
-        match args.ratio {
-            0 => u16::MAX,
-            51..=100 => 1,
-            34..=50 => 2,
-            26..=33 => 3,
-            21..=25 => 4,
-            18..=20 => 5,
-            16..=17 => 6,
-            14..=15 => 7,
-            x => 21 - x,
+/// The exit code of a runner, printing the error as a message rather than the `Debug` output of returning it
+pub fn exit_code<E: Into<Box<dyn Error>>>(result: Result<(), E>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e.into());
+            ExitCode::FAILURE
         }
     }
 }
@@ -101,34 +206,55 @@ pub fn print_how_many_true(bools: &[bool], args: &AppArgs) {
     println!("True: {percent:.2}% | {str_total_bools}/{str_n}");
 }
 
-/// Fill 2 vectors with random numbers and a vector of bools with a method specified on the command line
+/// Fill 2 vectors of the element type with random numbers and a vector of bools with the pattern specified on
+/// the command line
 #[inline(always)]
-pub fn fill_vecs(args: &AppArgs) -> (Vec<u32>, Vec<u32>, Vec<bool>) {
-    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(args.seed);
+pub fn fill_vecs(args: &AppArgs) -> AnyVecs {
+    with_element_type!(args.element, T => T::into_any(fill_typed_vecs::<T>(args)))
+}
 
-    // Get a u16 value from the 0-100% ratio specified on the command line
-    let ratio_val: u16 = arg_ratio_to_u16(args);
+#[inline(always)]
+fn fill_typed_vecs<T: Element>(args: &AppArgs) -> Vecs<T> {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(args.seed);
 
-    // If random comparison is enabled, use a random number generator to fill the boolean vector
-    // If not, use a modulo comparison to fill the boolean vector (deterministic comparison)
-    let comp_fn: fn(usize, &mut ChaCha8Rng, u16) -> bool = if args.rnd_cmp {
-        |_, rng: &mut ChaCha8Rng, ratio| (rng.gen::<u8>() as u16) < ratio
-    } else {
-        |i, _: &mut ChaCha8Rng, ratio| (i + 1) % ratio as usize == 0
-    };
+    let mut pattern = args.pattern.generator(args.ratio, args.N);
 
     // Create the vectors and fill them with random numbers and boolean values
-    let mut v1: Vec<u32> = vec![0; args.N];
-    let mut v2: Vec<u32> = vec![0; args.N];
+    let mut v1: Vec<T> = vec![T::ZERO; args.N];
+    let mut v2: Vec<T> = vec![T::ZERO; args.N];
     let mut bools: Vec<bool> = vec![false; args.N];
 
     for i in 0..args.N {
-        v1[i] = rng.gen();
-        v2[i] = rng.gen();
-        bools[i] = comp_fn(i, &mut rng, ratio_val);
+        v1[i] = T::random(&mut rng);
+        v2[i] = T::random(&mut rng);
+        bools[i] = pattern.next_bool(i, &mut rng);
     }
 
     print_how_many_true(&bools, args);
 
-    (v1, v2, bools)
+    Vecs { v1, v2, bools }
+}
+
+/// Fill the vectors as specified by the arguments and run the kernel on them
+///
+/// If perf stat controls the counters through FIFOs, they are only enabled around the `--phase`.
+#[inline(always)]
+pub fn run_kernel(kernel: &dyn Kernel, args: &AppArgs) -> io::Result<Checksum> {
+    let mut control = args.perf_control()?;
+
+    let (vecs, setup_time) = run_phase(&mut control, Phase::Setup, args.phase, || fill_vecs(args))?;
+
+    let (a, kernel_time) = run_phase(&mut control, Phase::Kernel, args.phase, || {
+        kernel.run(&vecs)
+    })?;
+    args.append_phase_duration(match args.phase {
+        Phase::Setup => setup_time,
+        Phase::Kernel => kernel_time,
+    })?;
+    args.write_metadata(vecs.bools(), Some(a))?;
+
+    if !args.quiet {
+        println!("Checksum: {a}");
+    }
+    Ok(a)
 }
@@ -1,13 +1,22 @@
 use clap::Parser;
-use regex::Regex;
 use rust_perf_comp::{
+    compare::compare,
     config::{Args, Command},
-    plot_perf_stats::{self, plot_vs_x},
+    exit_code,
+    metadata::RunMetadata,
+    metrics::{add_metric_records, Metric},
+    perf_stat::{distinct_events, read_perf_stat_file, Event},
+    plot_perf_stats::{
+        self, perf_stats_from_json_files, plot_events_vs_x, plot_perf_stats_vs_x,
+        warn_low_coverage, KernelNames, PlotOptions,
+    },
+    sweep::sweep,
 };
 use std::{
     error::Error,
     ffi::{OsStr, OsString},
     path::PathBuf,
+    process::ExitCode,
     sync::OnceLock,
 };
 
@@ -21,9 +30,11 @@ pub fn get_save_to() -> &'static OsStr {
     SAVE_TO.get().unwrap()
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+fn main() -> ExitCode {
+    exit_code(run(Args::parse()))
+}
 
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
     match args.cmd {
         Command::CleanPerfStatJson {
             input_file,
@@ -40,15 +51,28 @@ fn main() -> Result<(), Box<dyn Error>> {
             branchless_prefix,
             save_to,
             plot_type,
+            events,
+            metrics,
+            list_events,
+            over,
+            x_scale,
+            normalize,
+            coverage,
         } => {
             println!("Producing function over {x_vals:?}");
+            let x_vals = x_vals
+                .iter()
+                .map(|x| over.parse_value(x))
+                .collect::<Result<Vec<_>, _>>()?;
             println!("Using json-files from {json_dir:?} match patterns {branching_prefix} & {branchless_prefix} with the expected suffix of [x].json (e.g. {branching_prefix}0.json");
             assert!(json_dir.exists(), "{json_dir:?} does not exist");
             assert!(json_dir.is_dir(), "{json_dir:?} is not a directory");
 
             let mut branching_files = vec![];
             let mut branchless_files = vec![];
-            for x in &x_vals {
+            // The number of elements of the runs at every x, if they have a manifest
+            let mut elements = vec![];
+            for x in x_vals.iter().map(|x| over.file_suffix(*x)) {
                 let a = json_dir.join(PathBuf::from(format!("{branching_prefix}{x}.json")));
                 assert!(a.exists(), "{a:?} Does not exist - Expects {json_dir:?} to contain two files per value in x_vals, e.g. {branching_prefix}{x}.json & {branchless_prefix}{x}.json");
                 branching_files.push(a);
@@ -56,18 +80,89 @@ fn main() -> Result<(), Box<dyn Error>> {
                 let b = json_dir.join(PathBuf::from(format!("{branchless_prefix}{x}.json")));
                 assert!(b.exists(), "{b:?} Does not exist - Expects {json_dir:?} to contain two files per value in x_vals, e.g. {branching_prefix}{x}.json & {branchless_prefix}{x}.json");
                 branchless_files.push(b);
+
+                // The sweep stores the metadata of the runs next to the records, older directories don't have it
+                let read_metadata = |prefix: &str| {
+                    let path = json_dir.join(format!("{prefix}{x}.meta.json"));
+                    path.exists().then(|| RunMetadata::read(&path)).transpose()
+                };
+                let a_metadata = read_metadata(&branching_prefix)?;
+                let b_metadata = read_metadata(&branchless_prefix)?;
+                if let (Some(a_metadata), Some(b_metadata)) = (&a_metadata, &b_metadata) {
+                    b_metadata.check_checksum(a_metadata)?;
+                }
+                // Both ran on the same number of elements if the checksums could be checked
+                elements.push(a_metadata.or(b_metadata).map(|metadata| metadata.n));
+            }
+            if let Some(metric) = metrics.iter().find(|m| m.needs_elements()) {
+                if let Some((x, _)) = x_vals.iter().zip(&elements).find(|(_, n)| n.is_none()) {
+                    return Err(format!(
+                        "{metric} needs the number of elements from the run manifests, but {json_dir:?} has no {branching_prefix}{}.meta.json",
+                        over.file_suffix(*x)
+                    )
+                    .into());
+                }
             }
 
             set_save_to(save_to.into_os_string()).unwrap();
+            let x_axis = over.x_axis(&x_vals, x_scale);
+            let options = PlotOptions {
+                names: &KernelNames {
+                    baseline: &branching_prefix,
+                    candidate: &branchless_prefix,
+                },
+                x_axis: &x_axis,
+                coverage: &coverage,
+                normalize,
+                // perf doesn't record the `--repeat` count
+                repeats: &[],
+            };
 
-            plot_vs_x(
-                x_vals,
-                branching_files,
-                branchless_files,
-                get_save_to(),
-                plot_type,
-            )?;
+            let mut br_perf_stats = perf_stats_from_json_files(&branching_files)?;
+            let mut bl_perf_stats = perf_stats_from_json_files(&branchless_files)?;
+            if list_events {
+                for event in distinct_events(br_perf_stats.iter().chain(&bl_perf_stats).flatten()) {
+                    println!("{event}");
+                }
+                return Ok(());
+            }
+            warn_low_coverage(&branching_prefix, &br_perf_stats, &coverage);
+            warn_low_coverage(&branchless_prefix, &bl_perf_stats, &coverage);
+
+            // The metrics are plotted like the events, from records named after them
+            add_metric_records(&mut br_perf_stats, &elements, &metrics);
+            add_metric_records(&mut bl_perf_stats, &elements, &metrics);
+            let events: Vec<Event> = events
+                .into_iter()
+                .chain(metrics.iter().map(Metric::event))
+                .collect();
+
+            if !events.is_empty() {
+                plot_events_vs_x(
+                    x_vals,
+                    &br_perf_stats,
+                    &bl_perf_stats,
+                    &elements,
+                    &options,
+                    &events,
+                    get_save_to(),
+                )?;
+            } else {
+                // clap requires a plot type without events
+                let plot_type = plot_type.ok_or("missing --plot-type")?;
+                plot_perf_stats_vs_x(
+                    x_vals,
+                    &br_perf_stats,
+                    &bl_perf_stats,
+                    &elements,
+                    &options,
+                    get_save_to(),
+                    plot_type,
+                )?;
+            }
         }
+        Command::Sweep(sweep_args) => sweep(&sweep_args)?,
+        Command::Compare(compare_args) => compare(&compare_args)?,
     }
     Ok(())
 }
@@ -76,19 +171,10 @@ pub fn clean_perf_stat_json(
     in_file: PathBuf,
     output_file: Option<PathBuf>,
 ) -> Result<(), Box<dyn Error>> {
-    // read json file
     assert!(in_file.exists());
-    let json = std::fs::read_to_string(in_file)?;
-    // Replace all commas in numbers with dots
-    let re: Regex = Regex::new(r"(\d+),(\d+)")?;
-    let json = re.replace_all(&json, "$1.$2");
-    // Add a trailing comma to the end of each line except the last one
-    let re: Regex = Regex::new("(\n)")?;
-    let json = re.replace_all(&json, ",");
-    let json = json.trim_end_matches(',');
+    let records = read_perf_stat_file(&in_file)?;
 
-    // Add bracket to the start and end of the json
-    let final_json = format!("[{json}]");
+    let final_json = serde_json::to_string(&records)?;
     if let Some(out) = output_file {
         assert!(out.exists());
         std::fs::write(out, final_json)?;
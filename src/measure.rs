@@ -0,0 +1,118 @@
+use crate::{
+    element::{AnyVecs, Checksum},
+    kernel::Kernel,
+    perf_stat::{CounterValue, Event, PerfStatRecord},
+    stats::{reject_outliers, Estimate},
+};
+use std::{error::Error, fmt::Display, hint::black_box, str::FromStr, time::Instant};
+
+/// How the bench-runner measures the kernel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Measure {
+    /// Run the kernel once and leave the measuring to e.g. `perf stat`
+    #[default]
+    External,
+    /// Time the kernel in-process with [`Instant`], for when `perf` isn't available
+    Instant,
+    /// Count hardware events around the kernel with `perf_event_open`, see [`crate::perf_event`]
+    PerfEvent,
+}
+
+impl FromStr for Measure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "external" => Ok(Measure::External),
+            "instant" => Ok(Measure::Instant),
+            "perf-event" => Ok(Measure::PerfEvent),
+            _ => Err(format!(
+                "unknown measurement '{s}', expected 'external', 'instant' or 'perf-event'"
+            )),
+        }
+    }
+}
+
+impl Display for Measure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Measure::External => "external",
+            Measure::Instant => "instant",
+            Measure::PerfEvent => "perf-event",
+        })
+    }
+}
+
+/// Wall-clock durations of the kernel with the outliers removed
+#[derive(Debug, Clone)]
+pub struct Timing {
+    /// Durations in nanoseconds, in ascending order
+    pub samples_ns: Vec<f64>,
+    /// Number of iterations that were rejected as outliers
+    pub rejected: usize,
+}
+
+impl Timing {
+    /// The mean duration as the `duration_time` record `perf stat --repeat` would output
+    pub fn to_record(&self) -> Result<PerfStatRecord, Box<dyn Error>> {
+        record_from_samples(
+            Event::from_str("duration_time")?,
+            "ns",
+            &self.samples_ns,
+            self.samples_ns.iter().sum::<f64>() as u64,
+            100.,
+        )
+    }
+}
+
+/// A record with the mean of the samples, and their relative standard error as the variance like `perf stat --repeat`
+pub(crate) fn record_from_samples(
+    event: Event,
+    unit: &str,
+    samples: &[f64],
+    event_runtime: u64,
+    pcnt_running: f32,
+) -> Result<PerfStatRecord, Box<dyn Error>> {
+    let estimate =
+        Estimate::from_samples(samples).ok_or_else(|| format!("no samples of {event}"))?;
+    Ok(PerfStatRecord {
+        counter_value: CounterValue::Counted(estimate.mean),
+        unit: unit.to_string(),
+        event,
+        variance: Some(estimate.rel_sem_pct() as f32),
+        event_runtime,
+        pcnt_running,
+        metric: None,
+        phase: None,
+    })
+}
+
+/// Time `iterations` runs of the kernel after `warmup` untimed runs, rejecting outliers with Tukey's fences
+///
+/// Returns the checksum of the last run along with the timing.
+pub fn time_kernel(
+    kernel: &dyn Kernel,
+    vecs: &AnyVecs,
+    warmup: u32,
+    iterations: u32,
+) -> (Timing, Option<Checksum>) {
+    for _ in 0..warmup {
+        black_box(kernel.run(vecs));
+    }
+
+    let mut checksum = None;
+    let samples: Vec<f64> = (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            checksum = Some(black_box(kernel.run(black_box(vecs))));
+            start.elapsed().as_nanos() as f64
+        })
+        .collect();
+
+    let samples_ns = reject_outliers(&samples);
+    let timing = Timing {
+        rejected: samples.len() - samples_ns.len(),
+        samples_ns,
+    };
+    (timing, checksum)
+}
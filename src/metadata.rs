@@ -0,0 +1,180 @@
+use crate::{
+    element::{Checksum, ElementType},
+    kernel::find_kernel,
+    AppArgs,
+};
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path};
+
+/// The manifest of a run of the runner: what it was asked to do, what it actually generated and computed, and
+/// the build and machine it ran on, so the results can be reproduced later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub kernel: String,
+    /// The element type of the vectors, older metadata is from before there was a choice
+    #[serde(default)]
+    pub element: ElementType,
+    pub seed: u64,
+    #[serde(rename = "N")]
+    pub n: usize,
+    /// False with `--no-rnd-cmp`
+    #[serde(default = "default_rnd_cmp")]
+    pub rnd_cmp: bool,
+    pub pattern: String,
+    /// The requested ratio of true values in percent
+    pub requested_ratio: f64,
+    /// The ratio of true values in the generated bools in percent
+    pub achieved_ratio: f64,
+    pub true_count: usize,
+    /// What the kernel summed up to, if it ran
+    #[serde(default)]
+    pub checksum: Option<Checksum>,
+    #[serde(default)]
+    pub build: BuildInfo,
+    #[serde(default)]
+    pub host: HostInfo,
+}
+
+fn default_rnd_cmp() -> bool {
+    true
+}
+
+impl RunMetadata {
+    pub fn new(args: &AppArgs, bools: &[bool], checksum: Option<Checksum>) -> Self {
+        let true_count = bools.iter().filter(|&&b| b).count();
+        Self {
+            kernel: args.kernel.clone(),
+            element: args.element,
+            seed: args.seed,
+            n: args.N,
+            rnd_cmp: args.rnd_cmp,
+            pattern: args.pattern.to_string(),
+            requested_ratio: args.ratio,
+            achieved_ratio: true_count as f64 / bools.len().max(1) as f64 * 100.,
+            true_count,
+            checksum,
+            build: BuildInfo::current(),
+            host: HostInfo::current(),
+        }
+    }
+
+    /// If the runs filled the same vectors, which every kernel has to arrive at the same checksum on
+    fn same_vectors(&self, other: &RunMetadata) -> bool {
+        self.element == other.element
+            && self.seed == other.seed
+            && self.n == other.n
+            && self.rnd_cmp == other.rnd_cmp
+            && self.pattern == other.pattern
+            && self.requested_ratio == other.requested_ratio
+    }
+
+    /// If the checksum can be compared with the `reference` run's
+    ///
+    /// It can't if either is missing, or if they're rounded float sums and one of the kernels adds the values in
+    /// another order, e.g. `lane-select`. Kernels that add in order round the same way and arrive at the same sum.
+    pub fn checksum_is_comparable(&self, reference: &RunMetadata) -> bool {
+        let exact = |metadata: &RunMetadata| {
+            metadata
+                .checksum
+                .is_some_and(|checksum| checksum.is_exact(metadata.element))
+        };
+        let adds_in_order = |metadata: &RunMetadata| {
+            find_kernel(&metadata.kernel).is_some_and(|k| k.adds_in_order())
+        };
+        self.checksum.is_some()
+            && reference.checksum.is_some()
+            && ((exact(self) && exact(reference))
+                || (adds_in_order(self) && adds_in_order(reference)))
+    }
+
+    /// Check that the kernel of this run summed to the same checksum as the kernel of the `reference` run
+    ///
+    /// Checksums that can't be compared pass, see [`RunMetadata::checksum_is_comparable`].
+    pub fn check_checksum(&self, reference: &RunMetadata) -> Result<(), String> {
+        if !self.same_vectors(reference) {
+            return Err(format!(
+                "The {} and {} kernels did not run on the same vectors, the seed, N, ratio, pattern or element differ",
+                self.kernel, reference.kernel
+            ));
+        }
+        match (self.checksum, reference.checksum) {
+            (Some(checksum), Some(reference_checksum))
+                if self.checksum_is_comparable(reference) && checksum != reference_checksum =>
+            {
+                Err(format!(
+                    "The {} kernel summed to {checksum} but the {} kernel to {reference_checksum} with seed {}, N {} and a ratio of {}%",
+                    self.kernel, reference.kernel, self.seed, self.n, self.requested_ratio
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// How the runner was built, recorded by `build.rs`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// The commit with a `-dirty` suffix if there were uncommitted changes
+    pub git_commit: String,
+    pub rustc_version: String,
+    /// The target triple, e.g. `x86_64-unknown-linux-gnu`
+    pub target: String,
+    /// The `-C target-cpu` the runner was built with, `default` if none was given
+    pub target_cpu: String,
+    /// The target features enabled at compile time, e.g. `avx2` with `-C target-cpu=native`
+    pub target_features: Vec<String>,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            git_commit: env!("BUILD_GIT_COMMIT").to_string(),
+            rustc_version: env!("BUILD_RUSTC_VERSION").to_string(),
+            target: env!("BUILD_TARGET").to_string(),
+            target_cpu: env!("BUILD_TARGET_CPU").to_string(),
+            target_features: env!("BUILD_TARGET_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// The machine the runner ran on, the fields are `None` where the system doesn't expose them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: Option<String>,
+    /// The model name of the first CPU in `/proc/cpuinfo`
+    pub cpu_model: Option<String>,
+    /// The cpufreq governor of cpu0, e.g. `performance` or `powersave`
+    pub governor: Option<String>,
+}
+
+impl HostInfo {
+    pub fn current() -> Self {
+        let read = |path: &str| {
+            std::fs::read_to_string(path)
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        Self {
+            hostname: read("/proc/sys/kernel/hostname"),
+            cpu_model: read("/proc/cpuinfo").and_then(|cpuinfo| {
+                cpuinfo.lines().find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    (key.trim() == "model name").then(|| value.trim().to_string())
+                })
+            }),
+            governor: read("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+        }
+    }
+}
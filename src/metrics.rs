@@ -0,0 +1,214 @@
+//! Quantities derived from the counters of a run, e.g. the instructions per cycle or the cycles per element
+//!
+//! The metrics are derived per PMU, as the counts of different PMUs are of different parts of the run. They can be
+//! turned into records like the counters', named after the metric, to compare and plot them like the events.
+use crate::{
+    perf_stat::{CounterValue, Event, PerfStatRecord},
+    stats::Estimate,
+};
+use std::{fmt::Display, str::FromStr};
+
+/// A metric derived from the counters of each PMU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Instructions per cycle
+    Ipc,
+    /// Cycles per instruction
+    Cpi,
+    /// Mispredicted branches in percent of all branches
+    BranchMissPct,
+    /// Mispredicted branches per 1000 instructions
+    Mpki,
+    BranchesPerElement,
+    InstructionsPerElement,
+    CyclesPerElement,
+}
+
+/// What the numerator of a metric is divided by
+enum Denominator {
+    /// Another event, counted on the same PMU with the same modifiers and in the same phase
+    Event(&'static str),
+    /// The number of elements in the vectors, from the run manifest
+    Elements,
+}
+
+/// A metric of the counters of one PMU
+#[derive(Debug, Clone)]
+pub struct PmuMetric<'a> {
+    /// The record of the numerator, with the PMU, modifiers and phase the metric is of
+    pub numerator: &'a PerfStatRecord,
+    /// The lowest percentage of the time the counters of the metric ran
+    pub pcnt_running: f32,
+    pub value: Estimate,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 7] = [
+        Metric::Ipc,
+        Metric::Cpi,
+        Metric::BranchMissPct,
+        Metric::Mpki,
+        Metric::BranchesPerElement,
+        Metric::InstructionsPerElement,
+        Metric::CyclesPerElement,
+    ];
+
+    /// The numerator event, the denominator and the factor the ratio is scaled by
+    fn definition(&self) -> (&'static str, Denominator, f64) {
+        match self {
+            Metric::Ipc => ("instructions", Denominator::Event("cycles"), 1.),
+            Metric::Cpi => ("cycles", Denominator::Event("instructions"), 1.),
+            Metric::BranchMissPct => ("branch-misses", Denominator::Event("branches"), 100.),
+            Metric::Mpki => ("branch-misses", Denominator::Event("instructions"), 1000.),
+            Metric::BranchesPerElement => ("branches", Denominator::Elements, 1.),
+            Metric::InstructionsPerElement => ("instructions", Denominator::Elements, 1.),
+            Metric::CyclesPerElement => ("cycles", Denominator::Elements, 1.),
+        }
+    }
+
+    /// A description for tables, e.g. `IPC` or `branch-miss rate [%]`
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Ipc => "IPC",
+            Metric::Cpi => "CPI",
+            Metric::BranchMissPct => "branch-miss rate [%]",
+            Metric::Mpki => "branch MPKI",
+            Metric::BranchesPerElement => "branches per element",
+            Metric::InstructionsPerElement => "instructions per element",
+            Metric::CyclesPerElement => "cycles per element",
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Metric::BranchMissPct => "%",
+            _ => "",
+        }
+    }
+
+    /// Whether the metric needs the number of elements, which perf doesn't know
+    pub fn needs_elements(&self) -> bool {
+        matches!(self.definition().1, Denominator::Elements)
+    }
+
+    /// The event the records of the metric are named after, e.g. `ipc`
+    pub fn event(&self) -> Event {
+        Event {
+            pmu: None,
+            name: self.to_string(),
+            modifiers: String::new(),
+            trailing_modifiers: String::new(),
+        }
+    }
+
+    /// The metric of every PMU that counted its inputs in the records of a run, e.g. `cpu_core` and `cpu_atom`
+    ///
+    /// The per-element metrics are only derived if the number of elements is known.
+    pub fn per_pmu<'a>(
+        &self,
+        records: &'a [PerfStatRecord],
+        runs: u32,
+        elements: Option<usize>,
+    ) -> Vec<PmuMetric<'a>> {
+        let (numerator, denominator, scale) = self.definition();
+        records
+            .iter()
+            .filter(|r| r.event.name == numerator)
+            .filter_map(|num| {
+                let n = Estimate::from_record(num, runs)?;
+                let (value, pcnt_running) = match denominator {
+                    Denominator::Event(name) => {
+                        let den_event = Event {
+                            name: name.to_string(),
+                            ..num.event.clone()
+                        };
+                        let den = records
+                            .iter()
+                            .find(|r| r.event == den_event && r.phase == num.phase)?;
+                        (
+                            n.ratio(&Estimate::from_record(den, runs)?),
+                            num.pcnt_running.min(den.pcnt_running),
+                        )
+                    }
+                    Denominator::Elements => (n.scale(1. / elements? as f64), num.pcnt_running),
+                };
+                Some(PmuMetric {
+                    numerator: num,
+                    pcnt_running,
+                    value: value.scale(scale),
+                })
+            })
+            .collect()
+    }
+
+    /// The metric of every PMU as records like the counters', e.g. `cpu_core/ipc:u/` for `cpu_core/instructions:u/`
+    pub fn records(
+        &self,
+        records: &[PerfStatRecord],
+        elements: Option<usize>,
+    ) -> Vec<PerfStatRecord> {
+        self.per_pmu(records, 0, elements)
+            .into_iter()
+            .map(|metric| PerfStatRecord {
+                counter_value: CounterValue::Counted(metric.value.mean),
+                unit: self.unit().to_string(),
+                event: Event {
+                    name: self.to_string(),
+                    ..metric.numerator.event.clone()
+                },
+                variance: Some(metric.value.rel_sem_pct() as f32),
+                event_runtime: metric.numerator.event_runtime,
+                pcnt_running: metric.pcnt_running,
+                metric: None,
+                phase: metric.numerator.phase,
+            })
+            .collect()
+    }
+}
+
+/// Add the records of the metrics to the records of every run, see [`Metric::records`]
+///
+/// `elements` is the number of elements of each run if it's known.
+pub fn add_metric_records(
+    perf_stat_runs: &mut [Vec<PerfStatRecord>],
+    elements: &[Option<usize>],
+    metrics: &[Metric],
+) {
+    for (records, elements) in perf_stat_runs.iter_mut().zip(elements) {
+        let metric_records: Vec<PerfStatRecord> = metrics
+            .iter()
+            .flat_map(|metric| metric.records(records, *elements))
+            .collect();
+        records.extend(metric_records);
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Metric::ALL
+            .into_iter()
+            .find(|m| m.to_string() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown metric '{s}', expected one of: {}",
+                    Metric::ALL.map(|m| m.to_string()).join(", ")
+                )
+            })
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Metric::Ipc => "ipc",
+            Metric::Cpi => "cpi",
+            Metric::BranchMissPct => "branch-miss-pct",
+            Metric::Mpki => "mpki",
+            Metric::BranchesPerElement => "branches-per-element",
+            Metric::InstructionsPerElement => "instructions-per-element",
+            Metric::CyclesPerElement => "cycles-per-element",
+        })
+    }
+}
@@ -0,0 +1,399 @@
+//! Generators of the pattern of true and false values the kernels branch on
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use std::{fmt::Display, str::FromStr};
+
+/// Parts per million in one percent
+const PPM_PER_PERCENT: f64 = 10_000.;
+
+/// Parse a ratio of true values, either in percent, e.g. `0.1` or `99.9%`, or in parts per million, e.g. `250ppm`
+///
+/// The ratio is returned in percent.
+pub fn parse_ratio(s: &str) -> Result<f64, String> {
+    let ratio = match s.strip_suffix("ppm") {
+        Some(ppm) => ppm.trim().parse::<f64>().map(|ppm| ppm / PPM_PER_PERCENT),
+        None => s.trim_end_matches('%').trim().parse::<f64>(),
+    }
+    .map_err(|e| format!("invalid ratio '{s}': {e}"))?;
+    if !(0. ..=100.).contains(&ratio) {
+        return Err(format!(
+            "the ratio '{s}' has to be within 0-100 % (0-1000000ppm)"
+        ));
+    }
+    Ok(ratio)
+}
+
+/// Generates the bools one at a time, interleaved with the values of the vectors drawn from the same RNG
+pub trait PatternGenerator {
+    /// The bool at index `i`, called for every index in order
+    fn next_bool(&mut self, i: usize, rng: &mut ChaCha8Rng) -> bool;
+}
+
+/// A pattern as selected on the command line, e.g. `random`, `runs:8` or `periodic:TTFTF`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Pattern {
+    /// Each bool is true with the probability of the ratio
+    #[default]
+    Random,
+    /// The true values spread as evenly as possible, hitting the ratio exactly
+    Deterministic,
+    /// Runs of `k` equal bools, each run true with the probability of the ratio
+    Runs(usize),
+    /// The pattern repeated, the ratio is ignored
+    Periodic(Vec<bool>),
+    /// A Markov chain that switches between true and false with the probability on average, and is true with
+    /// the probability of the ratio
+    Markov(f64),
+    /// All the false values followed by the true values
+    Sorted,
+    /// All the true values followed by the false values
+    ReverseSorted,
+    /// The reverse-sorted pattern with every bool flipped with the probability in percent
+    Noisy(f64),
+}
+
+impl Pattern {
+    /// The patterns with a description of their parameter
+    pub const NAMES: [&'static str; 8] = [
+        "random",
+        "deterministic",
+        "runs:<K>",
+        "periodic:<TTFTF>",
+        "markov:<SWITCH PROBABILITY>",
+        "sorted",
+        "reverse-sorted",
+        "noisy:<NOISE %>",
+    ];
+
+    /// The generator of `n` bools of which roughly `ratio` percent are true
+    pub fn generator(&self, ratio: f64, n: usize) -> Box<dyn PatternGenerator> {
+        let frac_true = ratio / 100.;
+        let trues = (n as f64 * frac_true).round() as usize;
+        match self {
+            Pattern::Random => Box::new(RandomThreshold::new(ratio)),
+            Pattern::Deterministic => Box::new(ErrorDiffusion::new(ratio)),
+            Pattern::Runs(k) => Box::new(Runs {
+                length: *k,
+                threshold: RandomThreshold::new(ratio),
+                current: false,
+            }),
+            Pattern::Periodic(period) => Box::new(Periodic(period.clone())),
+            Pattern::Markov(switch) => Box::new(Markov::new(*switch, frac_true)),
+            Pattern::Sorted => Box::new(Sorted {
+                trues_from: n.saturating_sub(trues),
+            }),
+            Pattern::ReverseSorted => Box::new(ReverseSorted { trues }),
+            Pattern::Noisy(pct) => Box::new(Noisy {
+                pattern: ReverseSorted { trues },
+                noise: pct / 100.,
+            }),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, param) = match s.split_once(':') {
+            Some((name, param)) => (name, Some(param)),
+            None => (s, None),
+        };
+        let probability = |max: f64| -> Result<f64, String> {
+            let p: f64 = param
+                .ok_or_else(|| format!("'{name}' needs a parameter, e.g. '{name}:{}'", max / 10.))?
+                .parse()
+                .map_err(|e| format!("invalid parameter in '{s}': {e}"))?;
+            if !(0. ..=max).contains(&p) {
+                return Err(format!("the parameter of '{s}' has to be within 0-{max}"));
+            }
+            Ok(p)
+        };
+        let pattern = match name {
+            "random" => Pattern::Random,
+            // `modulo` is what the deterministic pattern used to be
+            "deterministic" | "modulo" => Pattern::Deterministic,
+            "runs" => {
+                let k: usize = param
+                    .ok_or("'runs' needs a run length, e.g. 'runs:8'")?
+                    .parse()
+                    .map_err(|e| format!("invalid run length in '{s}': {e}"))?;
+                if k == 0 {
+                    return Err(String::from("the run length has to be at least 1"));
+                }
+                Pattern::Runs(k)
+            }
+            "periodic" => {
+                let period = param.ok_or("'periodic' needs a period, e.g. 'periodic:TTFTF'")?;
+                if period.is_empty() {
+                    return Err(String::from("the period can't be empty"));
+                }
+                let period = period
+                    .chars()
+                    .map(|c| match c.to_ascii_uppercase() {
+                        'T' => Ok(true),
+                        'F' => Ok(false),
+                        _ => Err(format!(
+                            "invalid '{c}' in '{s}', the period can only be T or F"
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Pattern::Periodic(period)
+            }
+            "markov" => Pattern::Markov(probability(1.)?),
+            "sorted" => Pattern::Sorted,
+            "reverse-sorted" => Pattern::ReverseSorted,
+            "noisy" => Pattern::Noisy(probability(100.)?),
+            _ => {
+                return Err(format!(
+                    "unknown pattern '{s}', expected one of: {}",
+                    Pattern::NAMES.join(", ")
+                ))
+            }
+        };
+        let takes_param = matches!(
+            pattern,
+            Pattern::Runs(_) | Pattern::Periodic(_) | Pattern::Markov(_) | Pattern::Noisy(_)
+        );
+        if param.is_some() && !takes_param {
+            return Err(format!("'{name}' doesn't take a parameter"));
+        }
+        Ok(pattern)
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Random => f.write_str("random"),
+            Pattern::Deterministic => f.write_str("deterministic"),
+            Pattern::Runs(k) => write!(f, "runs:{k}"),
+            Pattern::Periodic(period) => {
+                f.write_str("periodic:")?;
+                for b in period {
+                    f.write_str(if *b { "T" } else { "F" })?;
+                }
+                Ok(())
+            }
+            Pattern::Markov(switch) => write!(f, "markov:{switch}"),
+            Pattern::Sorted => f.write_str("sorted"),
+            Pattern::ReverseSorted => f.write_str("reverse-sorted"),
+            Pattern::Noisy(pct) => write!(f, "noisy:{pct}"),
+        }
+    }
+}
+
+/// True if a random `u32` is below the threshold derived from the ratio
+///
+/// The threshold has a resolution of 2^-32, so ratios down to fractions of a ppm are honoured.
+pub struct RandomThreshold {
+    threshold: u64,
+}
+
+impl RandomThreshold {
+    pub fn new(ratio: f64) -> Self {
+        let range = u32::MAX as f64 + 1.;
+        let threshold = (ratio / 100. * range).round().clamp(0., range) as u64;
+        Self { threshold }
+    }
+}
+
+impl PatternGenerator for RandomThreshold {
+    fn next_bool(&mut self, _: usize, rng: &mut ChaCha8Rng) -> bool {
+        (rng.gen::<u32>() as u64) < self.threshold
+    }
+}
+
+/// True whenever the running count of true values falls behind the ratio, like Bresenham's line algorithm
+///
+/// Of the first `i` bools exactly `floor(i * ppm / 1_000_000)` are true, so the ratio is hit to within one bool
+/// once it's rounded to parts per million.
+pub struct ErrorDiffusion {
+    ppm: u64,
+}
+
+impl ErrorDiffusion {
+    pub fn new(ratio: f64) -> Self {
+        Self {
+            ppm: (ratio * PPM_PER_PERCENT).round() as u64,
+        }
+    }
+}
+
+impl PatternGenerator for ErrorDiffusion {
+    fn next_bool(&mut self, i: usize, _: &mut ChaCha8Rng) -> bool {
+        let trues_until = |i: usize| i as u64 * self.ppm / 1_000_000;
+        trues_until(i + 1) > trues_until(i)
+    }
+}
+
+pub struct Runs {
+    length: usize,
+    threshold: RandomThreshold,
+    current: bool,
+}
+
+impl PatternGenerator for Runs {
+    fn next_bool(&mut self, i: usize, rng: &mut ChaCha8Rng) -> bool {
+        if i.is_multiple_of(self.length) {
+            self.current = self.threshold.next_bool(i, rng);
+        }
+        self.current
+    }
+}
+
+pub struct Periodic(Vec<bool>);
+
+impl PatternGenerator for Periodic {
+    fn next_bool(&mut self, i: usize, _: &mut ChaCha8Rng) -> bool {
+        self.0[i % self.0.len()]
+    }
+}
+
+pub struct Markov {
+    true_to_false: f64,
+    false_to_true: f64,
+    state: Option<bool>,
+    frac_true: f64,
+}
+
+impl Markov {
+    /// A chain that is true `frac_true` of the time and switches with the probability `switch` on average
+    ///
+    /// The transition probabilities are clamped to 1, so a high switch probability with a ratio far from 50%
+    /// switches less often than requested.
+    pub fn new(switch: f64, frac_true: f64) -> Self {
+        // Never switch into a state that has to be avoided entirely at 0% and 100%
+        let transition = |frac_in_state: f64, frac_in_target: f64| {
+            if frac_in_target <= 0. {
+                0.
+            } else {
+                (switch / (2. * frac_in_state)).min(1.)
+            }
+        };
+        Self {
+            true_to_false: transition(frac_true, 1. - frac_true),
+            false_to_true: transition(1. - frac_true, frac_true),
+            state: None,
+            frac_true,
+        }
+    }
+}
+
+impl PatternGenerator for Markov {
+    fn next_bool(&mut self, _: usize, rng: &mut ChaCha8Rng) -> bool {
+        let state = match self.state {
+            // Start from the stationary distribution
+            None => rng.gen::<f64>() < self.frac_true,
+            Some(true) => rng.gen::<f64>() >= self.true_to_false,
+            Some(false) => rng.gen::<f64>() < self.false_to_true,
+        };
+        self.state = Some(state);
+        state
+    }
+}
+
+pub struct Sorted {
+    trues_from: usize,
+}
+
+impl PatternGenerator for Sorted {
+    fn next_bool(&mut self, i: usize, _: &mut ChaCha8Rng) -> bool {
+        i >= self.trues_from
+    }
+}
+
+pub struct ReverseSorted {
+    trues: usize,
+}
+
+impl PatternGenerator for ReverseSorted {
+    fn next_bool(&mut self, i: usize, _: &mut ChaCha8Rng) -> bool {
+        i < self.trues
+    }
+}
+
+pub struct Noisy {
+    pattern: ReverseSorted,
+    noise: f64,
+}
+
+impl PatternGenerator for Noisy {
+    fn next_bool(&mut self, i: usize, rng: &mut ChaCha8Rng) -> bool {
+        let b = self.pattern.next_bool(i, rng);
+        if rng.gen::<f64>() < self.noise {
+            !b
+        } else {
+            b
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn count_trues(pattern: &Pattern, ratio: f64, n: usize) -> usize {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut generator = pattern.generator(ratio, n);
+        (0..n).filter(|&i| generator.next_bool(i, &mut rng)).count()
+    }
+
+    #[test]
+    fn deterministic_hits_the_ratio_exactly() {
+        assert_eq!(
+            count_trues(&Pattern::Deterministic, 33.3333, 3_000_000),
+            999_999
+        );
+        assert_eq!(count_trues(&Pattern::Deterministic, 0.1, 10_000), 10);
+        assert_eq!(count_trues(&Pattern::Deterministic, 0., 10_000), 0);
+        assert_eq!(count_trues(&Pattern::Deterministic, 100., 10_000), 10_000);
+    }
+
+    #[test]
+    fn parse_ratio_in_percent_and_ppm() {
+        assert_eq!(parse_ratio("0.1"), Ok(0.1));
+        assert_eq!(parse_ratio("99.9%"), Ok(99.9));
+        assert_eq!(parse_ratio("250ppm"), Ok(0.025));
+        assert_eq!(parse_ratio("1000000ppm"), Ok(100.));
+        assert!(parse_ratio("100.1").is_err());
+        assert!(parse_ratio("-1%").is_err());
+        assert!(parse_ratio("1000001ppm").is_err());
+        assert!(parse_ratio("half").is_err());
+    }
+
+    #[test]
+    fn patterns_round_trip() {
+        for s in [
+            "random",
+            "deterministic",
+            "runs:8",
+            "periodic:TTFTF",
+            "markov:0.25",
+            "sorted",
+            "reverse-sorted",
+            "noisy:1.5",
+        ] {
+            let pattern: Pattern = s.parse().unwrap();
+            assert_eq!(pattern.to_string(), s);
+            assert_eq!(pattern.to_string().parse::<Pattern>(), Ok(pattern));
+        }
+        assert_eq!("modulo".parse::<Pattern>(), Ok(Pattern::Deterministic));
+    }
+
+    #[test]
+    fn reject_invalid_patterns() {
+        for s in [
+            "runs:0",
+            "periodic:",
+            "periodic:TXF",
+            "markov:1.5",
+            "noisy",
+            "sorted:1",
+            "zigzag",
+        ] {
+            assert!(s.parse::<Pattern>().is_err(), "{s}");
+        }
+    }
+}
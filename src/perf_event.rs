@@ -0,0 +1,384 @@
+//! Counting events with `perf_event_open(2)` from inside the runner, so only the kernel is counted and not the setup
+use crate::{
+    element::{AnyVecs, Checksum},
+    kernel::Kernel,
+    measure::record_from_samples,
+    perf_stat::{Event, PerfStatRecord},
+};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsRawFd, FromRawFd},
+    path::Path,
+    time::Instant,
+};
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+
+const PERF_COUNT_HW_CPU_CYCLES: u64 = 0;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_HW_BRANCH_INSTRUCTIONS: u64 = 4;
+const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+
+const PERF_COUNT_SW_TASK_CLOCK: u64 = 1;
+const PERF_COUNT_SW_CONTEXT_SWITCHES: u64 = 3;
+
+const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
+const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
+
+const ATTR_FLAG_DISABLED: u64 = 1 << 0;
+const ATTR_FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const ATTR_FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+
+/// Where the kernel lists the PMUs, each with the perf `type` to open its events with
+const PMU_DEVICES: &str = "/sys/bus/event_source/devices";
+
+/// `struct perf_event_attr` up to `PERF_ATTR_SIZE_VER5`, the bit fields are all in `flags`
+#[repr(C)]
+#[derive(Debug, Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+/// An event that can be counted with `perf_event_open`
+#[derive(Debug, Clone)]
+pub struct CounterSpec {
+    /// The event as perf would name it, e.g. `cpu_core/instructions:u/`
+    pub event: Event,
+    /// The unit as perf would report it
+    pub unit: &'static str,
+    type_: u32,
+    config: u64,
+    /// Scale from the raw count to the unit, e.g. from ns to msec for `task-clock`
+    scale: f64,
+}
+
+impl CounterSpec {
+    fn new(pmu: Option<&str>, name: &str, type_: u32, config: u64) -> Self {
+        Self {
+            event: Event {
+                pmu: pmu.map(str::to_string),
+                name: name.to_string(),
+                // The kernel is excluded so it works with the default `perf_event_paranoid`
+                modifiers: String::from("u"),
+                trailing_modifiers: String::new(),
+            },
+            unit: "",
+            type_,
+            config,
+            scale: 1.,
+        }
+    }
+}
+
+const HARDWARE_EVENTS: [(&str, u64); 4] = [
+    ("cycles", PERF_COUNT_HW_CPU_CYCLES),
+    ("instructions", PERF_COUNT_HW_INSTRUCTIONS),
+    ("branches", PERF_COUNT_HW_BRANCH_INSTRUCTIONS),
+    ("branch-misses", PERF_COUNT_HW_BRANCH_MISSES),
+];
+
+/// The PMUs of the CPU cores along with their perf type, e.g. `cpu_core` & `cpu_atom` on a hybrid Intel CPU,
+/// `armv8_pmuv3_0` & `armv8_pmuv3_1` on an ARM big.LITTLE one, or just `cpu` on most others
+///
+/// Like perf, a PMU is taken to be a core PMU if it's called `cpu` or lists the CPUs it covers in `cpus`, uncore
+/// PMUs only have a `cpumask`.
+pub fn core_pmus() -> Vec<(String, u32)> {
+    let Ok(devices) = std::fs::read_dir(PMU_DEVICES) else {
+        return vec![];
+    };
+    let mut pmus: Vec<(String, u32)> = devices
+        .flatten()
+        .filter_map(|d| {
+            let name = d.file_name().into_string().ok()?;
+            if name != "cpu" && !d.path().join("cpus").exists() {
+                return None;
+            }
+            let type_ = std::fs::read_to_string(d.path().join("type")).ok()?;
+            Some((name, type_.trim().parse().ok()?))
+        })
+        .collect();
+    pmus.sort();
+    pmus
+}
+
+/// Cycles, instructions, branches and branch-misses, on every core PMU if there's more than one
+pub fn hardware_counters() -> Vec<CounterSpec> {
+    let pmus = core_pmus();
+    if pmus.len() <= 1 {
+        return HARDWARE_EVENTS
+            .iter()
+            .map(|&(name, hw_id)| CounterSpec::new(None, name, PERF_TYPE_HARDWARE, hw_id))
+            .collect();
+    }
+    // The PMU type goes in the upper 32 bits of the config to count a generic hardware event on that PMU
+    pmus.iter()
+        .flat_map(|(pmu, pmu_type)| {
+            HARDWARE_EVENTS.iter().map(move |&(name, hw_id)| {
+                let config = (*pmu_type as u64) << 32 | hw_id;
+                CounterSpec::new(Some(pmu), name, PERF_TYPE_HARDWARE, config)
+            })
+        })
+        .collect()
+}
+
+/// The software events to fall back to when there are no hardware counters, e.g. in a VM
+pub fn software_counters() -> Vec<CounterSpec> {
+    vec![
+        CounterSpec {
+            unit: "msec",
+            scale: 1e-6,
+            ..CounterSpec::new(
+                None,
+                "task-clock",
+                PERF_TYPE_SOFTWARE,
+                PERF_COUNT_SW_TASK_CLOCK,
+            )
+        },
+        CounterSpec::new(
+            None,
+            "context-switches",
+            PERF_TYPE_SOFTWARE,
+            PERF_COUNT_SW_CONTEXT_SWITCHES,
+        ),
+    ]
+}
+
+/// The value of a counter along with how long it was enabled and actually counting, in ns
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    pub value: u64,
+    pub time_enabled: u64,
+    pub time_running: u64,
+}
+
+impl Reading {
+    /// What was counted since the `earlier` reading of the same counter
+    pub fn since(&self, earlier: &Reading) -> Reading {
+        Reading {
+            value: self.value - earlier.value,
+            time_enabled: self.time_enabled - earlier.time_enabled,
+            time_running: self.time_running - earlier.time_running,
+        }
+    }
+
+    /// The value extrapolated to the whole time enabled, like perf does when counters are multiplexed
+    pub fn scaled(&self) -> f64 {
+        if self.time_running == 0 {
+            0.
+        } else {
+            self.value as f64 * self.time_enabled as f64 / self.time_running as f64
+        }
+    }
+}
+
+/// An open counter of the calling thread, created disabled
+pub struct Counter {
+    pub spec: CounterSpec,
+    file: File,
+}
+
+impl Counter {
+    pub fn open(spec: CounterSpec) -> io::Result<Self> {
+        let attr = PerfEventAttr {
+            type_: spec.type_,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config: spec.config,
+            read_format: PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING,
+            flags: ATTR_FLAG_DISABLED | ATTR_FLAG_EXCLUDE_KERNEL | ATTR_FLAG_EXCLUDE_HV,
+            ..Default::default()
+        };
+        // SAFETY: `attr` is a valid `perf_event_attr` of the size it claims, pid 0 & cpu -1 is the calling thread on
+        // any CPU and -1 is no group leader
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                0 as libc::pid_t,
+                -1 as libc::c_int,
+                -1 as libc::c_int,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the fd was just opened and nothing else owns it
+        let file = unsafe { File::from_raw_fd(fd as libc::c_int) };
+        Ok(Self { spec, file })
+    }
+
+    fn ioctl(&self, request: libc::c_ulong) -> io::Result<()> {
+        // SAFETY: the enable & disable ioctls take no pointer argument
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn enable(&self) -> io::Result<()> {
+        self.ioctl(PERF_EVENT_IOC_ENABLE)
+    }
+
+    pub fn disable(&self) -> io::Result<()> {
+        self.ioctl(PERF_EVENT_IOC_DISABLE)
+    }
+
+    pub fn read(&self) -> io::Result<Reading> {
+        let mut buf = [0u8; 24];
+        (&self.file).read_exact(&mut buf)?;
+        let field = |i: usize| u64::from_ne_bytes(buf[i * 8..(i + 1) * 8].try_into().unwrap());
+        Ok(Reading {
+            value: field(0),
+            time_enabled: field(1),
+            time_running: field(2),
+        })
+    }
+}
+
+/// The measurement of a single run
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub duration_ns: f64,
+    /// A reading per counter, in the same order as the counters
+    pub readings: Vec<Reading>,
+}
+
+/// Every counter that could be opened
+pub struct Counters(Vec<Counter>);
+
+impl Counters {
+    /// Open the hardware counters, or the software events if none of the hardware counters are available
+    pub fn open() -> Result<Self, Box<dyn Error>> {
+        let mut last_err = None;
+        for specs in [hardware_counters(), software_counters()] {
+            let counters: Vec<Counter> = specs
+                .into_iter()
+                .filter_map(|spec| Counter::open(spec).map_err(|e| last_err = Some(e)).ok())
+                .collect();
+            if !counters.is_empty() {
+                return Ok(Self(counters));
+            }
+        }
+        let err = last_err.map_or_else(|| String::from("no events to count"), |e| e.to_string());
+        Err(format!(
+            "Failed to open any perf event: {err} - check {:?}",
+            Path::new("/proc/sys/kernel/perf_event_paranoid")
+        )
+        .into())
+    }
+
+    /// Count a single run of `f`, the counters are only enabled around it
+    pub fn count<T>(&self, f: impl FnOnce() -> T) -> io::Result<(Sample, T)> {
+        // The times enabled and running can't be reset, so take the difference of two readings
+        let before = self
+            .0
+            .iter()
+            .map(Counter::read)
+            .collect::<io::Result<Vec<_>>>()?;
+        let start = Instant::now();
+        for c in &self.0 {
+            c.enable()?;
+        }
+        let result = f();
+        for c in &self.0 {
+            c.disable()?;
+        }
+        let duration_ns = start.elapsed().as_nanos() as f64;
+        let readings = self
+            .0
+            .iter()
+            .zip(&before)
+            .map(|(c, before)| Ok(c.read()?.since(before)))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok((
+            Sample {
+                duration_ns,
+                readings,
+            },
+            result,
+        ))
+    }
+
+    /// A `duration_time` record followed by a record per counter, with the means of the samples
+    pub fn records(&self, samples: &[Sample]) -> Result<Vec<PerfStatRecord>, Box<dyn Error>> {
+        let durations_ns: Vec<f64> = samples.iter().map(|s| s.duration_ns).collect();
+        let mut records = vec![record_from_samples(
+            "duration_time".parse()?,
+            "ns",
+            &durations_ns,
+            durations_ns.iter().sum::<f64>() as u64,
+            100.,
+        )?];
+        for (i, c) in self.0.iter().enumerate() {
+            let readings: Vec<Reading> = samples.iter().map(|s| s.readings[i]).collect();
+            let values: Vec<f64> = readings.iter().map(|r| r.scaled() * c.spec.scale).collect();
+            let time_enabled: u64 = readings.iter().map(|r| r.time_enabled).sum();
+            let time_running: u64 = readings.iter().map(|r| r.time_running).sum();
+            let pcnt_running = if time_enabled == 0 {
+                0.
+            } else {
+                time_running as f32 / time_enabled as f32 * 100.
+            };
+            records.push(record_from_samples(
+                c.spec.event.clone(),
+                c.spec.unit,
+                &values,
+                time_running,
+                pcnt_running,
+            )?);
+        }
+        Ok(records)
+    }
+}
+
+/// Count `iterations` runs of the kernel after `warmup` uncounted runs
+///
+/// Returns the checksum of the last run along with the records.
+pub fn count_kernel(
+    counters: &Counters,
+    kernel: &dyn Kernel,
+    vecs: &AnyVecs,
+    warmup: u32,
+    iterations: u32,
+) -> Result<(Vec<PerfStatRecord>, Option<Checksum>), Box<dyn Error>> {
+    for _ in 0..warmup {
+        std::hint::black_box(kernel.run(vecs));
+    }
+
+    let mut checksum = None;
+    let samples = (0..iterations)
+        .map(|_| {
+            let (sample, a) = counters.count(|| kernel.run(vecs))?;
+            checksum = Some(std::hint::black_box(a));
+            Ok(sample)
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok((counters.records(&samples)?, checksum))
+}
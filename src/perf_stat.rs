@@ -0,0 +1,575 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+/// A single counter as reported by `perf stat`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawPerfStatRecord", into = "RawPerfStatRecord")]
+pub struct PerfStatRecord {
+    pub counter_value: CounterValue,
+    pub unit: String,
+    pub event: Event,
+    /// Relative standard deviation of the mean in percent, only present with `perf stat --repeat`
+    pub variance: Option<f32>,
+    pub event_runtime: u64,
+    pub pcnt_running: f32,
+    pub metric: Option<MetricValue>,
+    /// The phase of the runner that was measured, `None` if it was the whole process
+    pub phase: Option<Phase>,
+}
+
+impl PerfStatRecord {
+    /// The counted value, or 0 if perf didn't count it
+    pub fn value_or_zero(&self) -> f64 {
+        self.counter_value.counted().unwrap_or(0.)
+    }
+
+    /// Whether this is a measurement of the setup rather than of the kernel, or the whole process
+    pub fn is_setup(&self) -> bool {
+        self.phase == Some(Phase::Setup)
+    }
+
+    /// Whether perf counted it for only part of the time and scaled the count up to the whole run
+    ///
+    /// That happens when there are more events than counters and perf multiplexes them, or on a hybrid CPU for the
+    /// PMU of the cores the process mostly didn't run on.
+    pub fn is_extrapolated(&self) -> bool {
+        self.pcnt_running < 100.
+    }
+}
+
+/// A phase of the runner that can be measured on its own, see [`crate::phase`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Filling the vectors
+    Setup,
+    /// Running the kernel on the vectors
+    #[default]
+    Kernel,
+}
+
+impl FromStr for Phase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "setup" => Ok(Phase::Setup),
+            "kernel" => Ok(Phase::Kernel),
+            _ => Err(format!("unknown phase '{s}', expected 'setup' or 'kernel'")),
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Phase::Setup => "setup",
+            Phase::Kernel => "kernel",
+        })
+    }
+}
+
+/// The record exactly as perf outputs it, with every number we care about as a string
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RawPerfStatRecord {
+    counter_value: String,
+    #[serde(default)]
+    unit: String,
+    event: String,
+    variance: Option<f32>,
+    event_runtime: u64,
+    pcnt_running: f32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    metric_value: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    metric_unit: String,
+    /// Not output by perf, but added by the sweep and runner when they measure a single phase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    phase: Option<Phase>,
+}
+
+impl TryFrom<RawPerfStatRecord> for PerfStatRecord {
+    type Error = InvalidValue;
+
+    fn try_from(raw: RawPerfStatRecord) -> Result<Self, Self::Error> {
+        let metric = if raw.metric_value.is_empty() || raw.metric_unit.is_empty() {
+            None
+        } else {
+            Some(MetricValue {
+                value: raw
+                    .metric_value
+                    .parse()
+                    .map_err(|_| InvalidValue::new("metric-value", &raw.metric_value))?,
+                unit: raw.metric_unit,
+            })
+        };
+        Ok(Self {
+            counter_value: raw.counter_value.parse()?,
+            unit: raw.unit,
+            event: raw.event.parse()?,
+            variance: raw.variance,
+            event_runtime: raw.event_runtime,
+            pcnt_running: raw.pcnt_running,
+            metric,
+            phase: raw.phase,
+        })
+    }
+}
+
+impl From<PerfStatRecord> for RawPerfStatRecord {
+    fn from(record: PerfStatRecord) -> Self {
+        let (metric_value, metric_unit) = match record.metric {
+            Some(MetricValue { value, unit }) => (format!("{value:.6}"), unit),
+            None => (String::new(), String::new()),
+        };
+        Self {
+            counter_value: record.counter_value.to_string(),
+            unit: record.unit,
+            event: record.event.to_string(),
+            variance: record.variance,
+            event_runtime: record.event_runtime,
+            pcnt_running: record.pcnt_running,
+            metric_value,
+            metric_unit,
+            phase: record.phase,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CounterValue {
+    Counted(f64),
+    NotCounted,
+    NotSupported,
+}
+
+impl CounterValue {
+    pub const NOT_COUNTED: &'static str = "<not counted>";
+    pub const NOT_SUPPORTED: &'static str = "<not supported>";
+
+    pub fn counted(&self) -> Option<f64> {
+        match self {
+            CounterValue::Counted(v) => Some(*v),
+            CounterValue::NotCounted | CounterValue::NotSupported => None,
+        }
+    }
+}
+
+impl FromStr for CounterValue {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            Self::NOT_COUNTED => Ok(CounterValue::NotCounted),
+            Self::NOT_SUPPORTED => Ok(CounterValue::NotSupported),
+            _ => s
+                .parse()
+                .map(CounterValue::Counted)
+                .map_err(|_| InvalidValue::new("counter-value", s)),
+        }
+    }
+}
+
+impl Display for CounterValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CounterValue::Counted(v) => write!(f, "{v:.6}"),
+            CounterValue::NotCounted => f.write_str(Self::NOT_COUNTED),
+            CounterValue::NotSupported => f.write_str(Self::NOT_SUPPORTED),
+        }
+    }
+}
+
+/// A metric perf derived from the counter, e.g. `1.18` `insn per cycle`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricValue {
+    pub value: f64,
+    pub unit: String,
+}
+
+/// A perf event such as `cpu_core/instructions:u/`, `instructions:u` or `duration_time`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Event {
+    /// The PMU the event was counted on, e.g. `cpu_core`
+    pub pmu: Option<String>,
+    pub name: String,
+    /// Modifiers such as `u` (user space only), empty if there are none
+    pub modifiers: String,
+    /// Modifiers after the PMU's closing slash, as in `cpu/event=0x3c,umask=0x1/u`, empty if there are none
+    pub trailing_modifiers: String,
+}
+
+impl Event {
+    /// The characters perf accepts as event modifiers
+    pub const MODIFIERS: &'static str = "ukhIGHpPSDWeb";
+
+    /// Check if this event is the one described by `query`, e.g. `cpu_core/instructions` or `instructions:u`
+    ///
+    /// The PMU and modifiers are only compared if the query specifies them.
+    pub fn matches(&self, query: &Event) -> bool {
+        self.name == query.name
+            && (query.pmu.is_none() || self.pmu == query.pmu)
+            && (query.all_modifiers().is_empty() || self.all_modifiers() == query.all_modifiers())
+    }
+
+    /// The modifiers wherever they were written, e.g. `uk` for `cpu/instructions:u/k`
+    pub fn all_modifiers(&self) -> String {
+        format!("{}{}", self.modifiers, self.trailing_modifiers)
+    }
+}
+
+/// The distinct events of the records in the order they first appear, e.g. to list the events that can be plotted
+pub fn distinct_events<'a>(records: impl IntoIterator<Item = &'a PerfStatRecord>) -> Vec<Event> {
+    let mut events: Vec<Event> = vec![];
+    for record in records {
+        if !events.contains(&record.event) {
+            events.push(record.event.clone());
+        }
+    }
+    events
+}
+
+impl FromStr for Event {
+    type Err = InvalidValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Either `pmu/name[:modifiers]/[modifiers]` or `name[:modifiers]`
+        let (pmu, name_mods, trailing_mods) = match s.split_once('/') {
+            Some((pmu, rest)) => {
+                let (name_mods, trailing) = rest.rsplit_once('/').unwrap_or((rest, ""));
+                (Some(pmu.to_string()), name_mods, trailing)
+            }
+            None => (None, s, ""),
+        };
+        // Tracepoints also use `:`, as in `sched:sched_switch`, so only split off valid modifiers
+        let (name, mods) = match name_mods.rsplit_once(':') {
+            Some((name, mods))
+                if !mods.is_empty() && mods.chars().all(|c| Self::MODIFIERS.contains(c)) =>
+            {
+                (name, mods)
+            }
+            _ => (name_mods, ""),
+        };
+        if name.is_empty() {
+            return Err(InvalidValue::new("event", s));
+        }
+        Ok(Self {
+            pmu,
+            name: name.to_string(),
+            modifiers: mods.to_string(),
+            trailing_modifiers: trailing_mods.to_string(),
+        })
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(pmu) = &self.pmu {
+            write!(f, "{pmu}/")?;
+        }
+        f.write_str(&self.name)?;
+        if !self.modifiers.is_empty() {
+            write!(f, ":{}", self.modifiers)?;
+        }
+        if self.pmu.is_some() {
+            write!(f, "/{}", self.trailing_modifiers)?;
+        }
+        Ok(())
+    }
+}
+
+/// A field of a perf stat record that couldn't be parsed
+#[derive(Debug)]
+pub struct InvalidValue {
+    field: &'static str,
+    value: String,
+}
+
+impl InvalidValue {
+    fn new(field: &'static str, value: &str) -> Self {
+        Self {
+            field,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl Display for InvalidValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid {}: {:?}", self.field, self.value)
+    }
+}
+
+impl Error for InvalidValue {}
+
+/// Fields that perf outputs as quoted numbers, and may therefore contain a locale decimal comma
+const QUOTED_NUMERIC_FIELDS: [&str; 2] = ["counter-value", "metric-value"];
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(std::io::Error),
+    /// A line that looks like a record but isn't a valid one, `line` is 1-based
+    Record {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "failed reading perf stat output: {e}"),
+            ParseError::Record { line, source } => {
+                write!(f, "invalid perf stat record on line {line}: {source}")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            ParseError::Record { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Streams [`PerfStatRecord`]s from the line-delimited JSON that `perf stat --json-output` produces
+///
+/// Blank lines, `#` comments and any other line that isn't a JSON object (such as headers or output from the
+/// measured program) are skipped.
+pub struct PerfStatReader<R> {
+    reader: R,
+    line_no: usize,
+    buf: String,
+}
+
+impl<R: BufRead> PerfStatReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line_no: 0,
+            buf: String::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PerfStatReader<R> {
+    type Item = Result<PerfStatRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line_no += 1,
+                Err(e) => return Some(Err(ParseError::Io(e))),
+            }
+            let line = self.buf.trim();
+            if !line.starts_with('{') {
+                continue;
+            }
+            return Some(parse_record(line).map_err(|source| ParseError::Record {
+                line: self.line_no,
+                source,
+            }));
+        }
+    }
+}
+
+/// Parse all records in the output of `perf stat --json-output`
+pub fn parse_perf_stat_json(output: &str) -> Result<Vec<PerfStatRecord>, ParseError> {
+    PerfStatReader::new(output.as_bytes()).collect()
+}
+
+/// Read perf stat records from a file with either the raw `perf stat --json-output` or a JSON array of records
+pub fn read_perf_stat_file(path: &Path) -> Result<Vec<PerfStatRecord>, Box<dyn Error>> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    if reader.fill_buf()?.trim_ascii_start().starts_with(b"[") {
+        Ok(serde_json::from_reader(reader)?)
+    } else {
+        Ok(PerfStatReader::new(reader).collect::<Result<_, _>>()?)
+    }
+}
+
+fn parse_record(line: &str) -> Result<PerfStatRecord, serde_json::Error> {
+    let mut value: Value = serde_json::from_str(&fix_unquoted_decimal_commas(line))?;
+    if let Value::Object(fields) = &mut value {
+        for field in QUOTED_NUMERIC_FIELDS {
+            if let Some(v) = fields.get_mut(field) {
+                normalize_quoted_number(v);
+            }
+        }
+    }
+    serde_json::from_value(value)
+}
+
+/// Replace decimal commas in numbers outside of strings, e.g. `"variance" : 0,22` from a locale such as `de_DE`
+///
+/// In valid JSON a comma between two digits can only appear inside a string, so this doesn't touch any separators.
+fn fix_unquoted_decimal_commas(line: &str) -> String {
+    let mut fixed = String::with_capacity(line.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ','
+            && fixed.ends_with(|p: char| p.is_ascii_digit())
+            && chars.peek().is_some_and(char::is_ascii_digit)
+        {
+            fixed.push('.');
+            continue;
+        }
+        fixed.push(c);
+    }
+    fixed
+}
+
+/// Make sure a numeric field is a string with a decimal point, as older perf versions output some numbers unquoted
+fn normalize_quoted_number(v: &mut Value) {
+    match v {
+        Value::Number(n) => *v = Value::String(n.to_string()),
+        Value::String(s)
+            if s.contains(',')
+                && s.chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == '-') =>
+        {
+            *s = s.replacen(',', ".", 1);
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EN_US: &str = r#"# started on Thu Feb 29 10:00:00 2024
+
+Requested ratio: 50% | N: 10,000,000 | Seed=0
+{"counter-value" : "3481394123.000000", "unit" : "ns", "event" : "duration_time", "variance" : 0.37, "event-runtime" : 3481394123, "pcnt-running" : 100.00, "metric-value" : "0.000000", "metric-unit" : ""}
+{"counter-value" : "13148396527.000000", "unit" : "", "event" : "cpu_core/instructions:u/", "variance" : 0.06, "event-runtime" : 3480021100, "pcnt-running" : 99.63, "metric-value" : "1.18", "metric-unit" : "insn per cycle"}
+{"counter-value" : "<not counted>", "unit" : "", "event" : "cpu_atom/instructions:u/", "event-runtime" : 0, "pcnt-running" : 0.00}
+{"counter-value" : "<not supported>", "unit" : "", "event" : "cpu_atom/branch-misses:u/", "event-runtime" : 0, "pcnt-running" : 100.00}
+"#;
+
+    const DE_DE: &str = r#"# started on Do 29 Feb 2024 10:00:00 CET
+
+Requested ratio: 50% | N: 10,000,000 | Seed=0
+{"counter-value" : "3481394123,000000", "unit" : "ns", "event" : "duration_time", "variance" : 0,37, "event-runtime" : 3481394123, "pcnt-running" : 100,00, "metric-value" : "0,000000", "metric-unit" : ""}
+{"counter-value" : "13148396527,000000", "unit" : "", "event" : "cpu_core/instructions:u/", "variance" : 0,06, "event-runtime" : 3480021100, "pcnt-running" : 99,63, "metric-value" : "1,18", "metric-unit" : "insn per cycle"}
+{"counter-value" : "<not counted>", "unit" : "", "event" : "cpu_atom/instructions:u/", "event-runtime" : 0, "pcnt-running" : 0,00}
+{"counter-value" : "<not supported>", "unit" : "", "event" : "cpu_atom/branch-misses:u/", "event-runtime" : 0, "pcnt-running" : 100,00}
+"#;
+
+    #[test]
+    fn parse_en_us_records() {
+        let records = parse_perf_stat_json(EN_US).unwrap();
+        assert_eq!(records.len(), 4);
+
+        let instructions = &records[1];
+        assert_eq!(
+            instructions.event,
+            "cpu_core/instructions:u/".parse().unwrap()
+        );
+        assert_eq!(
+            instructions.counter_value,
+            CounterValue::Counted(13148396527.)
+        );
+        assert_eq!(instructions.variance, Some(0.06));
+        assert_eq!(instructions.pcnt_running, 99.63);
+        assert_eq!(
+            instructions.metric,
+            Some(MetricValue {
+                value: 1.18,
+                unit: String::from("insn per cycle"),
+            })
+        );
+        assert_eq!(records[2].counter_value, CounterValue::NotCounted);
+        assert_eq!(records[3].counter_value, CounterValue::NotSupported);
+    }
+
+    #[test]
+    fn parse_de_de_records_like_en_us() {
+        assert_eq!(
+            parse_perf_stat_json(DE_DE).unwrap(),
+            parse_perf_stat_json(EN_US).unwrap()
+        );
+    }
+
+    #[test]
+    fn report_the_line_of_a_malformed_record() {
+        let output = format!("{EN_US}{{\"counter-value\" : \"1\", broken\n");
+        match parse_perf_stat_json(&output) {
+            Err(ParseError::Record { line, .. }) => assert_eq!(line, 8),
+            other => panic!("expected an invalid record, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn fix_only_decimal_commas_outside_of_strings() {
+        assert_eq!(
+            fix_unquoted_decimal_commas(
+                r#"{"event" : "cpu/event=0x3c,umask=0x1/u", "metric-value" : "1,18", "variance" : 0,06, "x" : 1}"#
+            ),
+            r#"{"event" : "cpu/event=0x3c,umask=0x1/u", "metric-value" : "1,18", "variance" : 0.06, "x" : 1}"#
+        );
+    }
+
+    #[test]
+    fn normalize_quoted_numbers() {
+        let mut comma = Value::from("1,18");
+        normalize_quoted_number(&mut comma);
+        assert_eq!(comma, Value::from("1.18"));
+
+        let mut unquoted = serde_json::json!(1.18);
+        normalize_quoted_number(&mut unquoted);
+        assert_eq!(unquoted, Value::from("1.18"));
+
+        let mut not_counted = Value::from(CounterValue::NOT_COUNTED);
+        normalize_quoted_number(&mut not_counted);
+        assert_eq!(not_counted, Value::from(CounterValue::NOT_COUNTED));
+    }
+
+    #[test]
+    fn events_round_trip() {
+        for s in [
+            "duration_time",
+            "instructions:u",
+            "sched:sched_switch",
+            "cpu_core/instructions:u/",
+            "cpu/event=0x3c,umask=0x1/u",
+            "cpu/instructions:u/k",
+        ] {
+            let event: Event = s.parse().unwrap();
+            assert_eq!(event.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn match_modifiers_wherever_they_were_written() {
+        let event: Event = "cpu/instructions/u".parse().unwrap();
+        assert_eq!(event.all_modifiers(), "u");
+        assert!(event.matches(&"instructions:u".parse().unwrap()));
+        assert!(event.matches(&"cpu/instructions:u/".parse().unwrap()));
+        assert!(!event.matches(&"instructions:k".parse().unwrap()));
+    }
+}
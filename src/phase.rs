@@ -0,0 +1,166 @@
+//! Phase markers so `perf stat` only counts one phase of the runner, e.g. the kernel without the setup
+//!
+//! perf is started with its counters disabled (`--delay=-1`) and listens on a control FIFO
+//! (`--control=fifo:<ctl>,<ack>`). The runner writes `enable` and `disable` to it around the phase being measured and
+//! waits for perf to acknowledge each command, so nothing outside the phase is counted.
+//!
+//! perf's `duration_time` is the wall-clock time of the whole runner though, counters enabled or not, so the runner
+//! times the phase itself and appends it to a durations file next to the FIFOs.
+use crate::perf_stat::Phase;
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// What perf answers on the ack FIFO once it has executed a command
+const ACK: &str = "ack";
+
+/// The runner's end of `perf stat --control=fifo:<ctl>,<ack>`
+pub struct PerfControl {
+    ctl: File,
+    ack: BufReader<File>,
+}
+
+impl PerfControl {
+    /// Open the FIFOs that perf was started with, perf has to have opened them already
+    pub fn open(ctl: &Path, ack: &Path) -> io::Result<Self> {
+        Ok(Self {
+            ctl: OpenOptions::new().write(true).open(ctl)?,
+            ack: BufReader::new(File::open(ack)?),
+        })
+    }
+
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.command("enable")
+    }
+
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.command("disable")
+    }
+
+    fn command(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.ctl, "{command}")?;
+        self.ctl.flush()?;
+        let mut reply = String::new();
+        self.ack.read_line(&mut reply)?;
+        if reply.trim_end_matches(['\n', '\0']) != ACK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("perf didn't acknowledge '{command}', got {reply:?}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Run `f`, enabling the counters around it if it's the measured phase, along with how long `f` took
+pub fn run_phase<T>(
+    control: &mut Option<PerfControl>,
+    phase: Phase,
+    measured: Phase,
+    f: impl FnOnce() -> T,
+) -> io::Result<(T, Duration)> {
+    let timed = || {
+        let start = Instant::now();
+        let result = f();
+        (result, start.elapsed())
+    };
+    let Some(control) = control.as_mut().filter(|_| phase == measured) else {
+        return Ok(timed());
+    };
+    control.enable()?;
+    let result = timed();
+    control.disable()?;
+    Ok(result)
+}
+
+/// A control and an ack FIFO in a temporary directory, removed again when dropped
+pub struct ControlFifos {
+    dir: PathBuf,
+    pub ctl: PathBuf,
+    pub ack: PathBuf,
+    /// Where the runner appends the duration of the phase in ns, a line per run
+    pub durations: PathBuf,
+}
+
+impl ControlFifos {
+    pub fn create() -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("rust-perf-comp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let fifos = Self {
+            ctl: dir.join("ctl.fifo"),
+            ack: dir.join("ack.fifo"),
+            durations: dir.join("durations"),
+            dir,
+        };
+        mkfifo(&fifos.ctl)?;
+        mkfifo(&fifos.ack)?;
+        Ok(fifos)
+    }
+
+    /// The arguments that make perf start disabled and listen to the FIFOs
+    pub fn perf_args(&self) -> [String; 2] {
+        [
+            String::from("--delay=-1"),
+            format!(
+                "--control=fifo:{},{}",
+                self.ctl.display(),
+                self.ack.display()
+            ),
+        ]
+    }
+
+    /// The arguments that make the runner mark the phase on the FIFOs
+    pub fn runner_args(&self, phase: Phase) -> Vec<String> {
+        vec![
+            String::from("--control-fifo"),
+            self.ctl.display().to_string(),
+            String::from("--ack-fifo"),
+            self.ack.display().to_string(),
+            String::from("--phase"),
+            phase.to_string(),
+            String::from("--phase-durations"),
+            self.durations.display().to_string(),
+        ]
+    }
+
+    /// The durations of the phase the runner appended since the last call, in ns
+    pub fn take_durations(&self) -> io::Result<Vec<f64>> {
+        let durations = match std::fs::read_to_string(&self.durations) {
+            Ok(durations) => durations,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        std::fs::remove_file(&self.durations)?;
+        durations
+            .lines()
+            .map(|line| {
+                line.trim().parse().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid duration {line:?} in {:?}: {e}", self.durations),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+impl Drop for ControlFifos {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn mkfifo(path: &Path) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `path` is a valid NUL-terminated string
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
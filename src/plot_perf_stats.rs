@@ -4,54 +4,66 @@ use plotlib::{
     style::{LineJoin, LineStyle, PointMarker, PointStyle},
 };
 use plotters::{element::*, style::full_palette::PURPLE};
-use plotters::{element::ComposedElement, prelude::*};
-use serde::{Deserialize, Serialize};
+use plotters::{coord::types::RangedCoordf64, element::ComposedElement, prelude::*};
 use std::{
     error::Error,
     ffi::OsStr,
     fmt::{write, Display},
+    ops::Range,
     path::PathBuf,
 };
 
-use crate::config::PlotType;
+use crate::{
+    config::{Coverage, LowCoverage, Normalize, PlotType},
+    kernel::PASSES,
+    metrics::Metric,
+    perf_stat::{distinct_events, read_perf_stat_file, CounterValue, Event, PerfStatRecord},
+    stats::Estimate,
+};
 
+/// A line of counts on the CPU, summed over the core PMUs or on one of them
 #[derive(Clone, Copy)]
-pub enum Cpu {
+pub enum Cpu<'a> {
     Total,
-    Core,
-    Atom,
+    /// The PMU at `index` of the core PMUs in the records, e.g. `cpu_core`, `armv8_pmuv3_0` or `cpu`
+    Pmu {
+        index: usize,
+        name: &'a str,
+    },
 }
 
+/// A point marker with its y-value printed next to it
+pub type LabeledPoint<'a, Marker> =
+    ComposedElement<(f64, f64), SVGBackend<'a>, Marker, Text<'static, (i32, i32), String>>;
+pub type LabeledCircle<'a> = LabeledPoint<'a, Circle<(i32, i32), i32>>;
+pub type LabeledTriangle<'a> = LabeledPoint<'a, TriangleMarker<(i32, i32), i32>>;
+/// The (sub-)area a single chart is drawn on
+type PlotArea<'a> = DrawingArea<SVGBackend<'a>, plotters::coord::Shift>;
+type XyChart<'a, 'b> =
+    ChartContext<'a, SVGBackend<'b>, Cartesian2d<RangedCoordf64, RangedCoordf64>>;
+/// Values with their uncertainty over the x-axis
+type SeriesOverX = Vec<(f64, Estimate)>;
+/// The x of the points perf extrapolated, with the lowest percentage of the time a counter of the point ran
+type Extrapolated = Vec<(f64, f32)>;
+
 pub trait CpuPlotStyle {
     fn cpu_total_style() -> ShapeStyle;
-    fn cpu_core_style() -> ShapeStyle;
-    fn cpu_atom_style() -> ShapeStyle;
+    /// The style of the PMU at `index` of the core PMUs in the records
+    fn cpu_pmu_style(index: usize) -> ShapeStyle;
     fn branch_misses_style() -> ShapeStyle;
 
     fn cpu_total_legend_style() -> impl Fn((i32, i32)) -> PathElement<(i32, i32)> {
         |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], Self::cpu_total_style())
     }
-    fn cpu_core_legend_style() -> impl Fn((i32, i32)) -> PathElement<(i32, i32)> {
-        |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], Self::cpu_core_style())
-    }
-    fn cpu_atom_legend_style() -> impl Fn((i32, i32)) -> PathElement<(i32, i32)> {
-        |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], Self::cpu_atom_style())
+    fn cpu_pmu_legend_style(index: usize) -> impl Fn((i32, i32)) -> PathElement<(i32, i32)> {
+        move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], Self::cpu_pmu_style(index))
     }
 
     fn branch_misses_legend_style() -> impl Fn((i32, i32)) -> PathElement<(i32, i32)> {
         |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], Self::branch_misses_style())
     }
 
-    fn line_points_circle() -> impl Fn(
-        (f64, f64),
-        i32,
-        ShapeStyle,
-    ) -> ComposedElement<
-        (f64, f64),
-        SVGBackend<'static>,
-        Circle<(i32, i32), i32>,
-        Text<'static, (i32, i32), String>,
-    > {
+    fn line_points_circle<'a>() -> impl Fn((f64, f64), i32, ShapeStyle) -> LabeledCircle<'a> {
         |(x, y), s: i32, st: ShapeStyle| {
             // We want to construct a composed element on-the-fly
             EmptyElement::<(f64, f64), SVGBackend>::at((x, y))
@@ -61,16 +73,7 @@ pub trait CpuPlotStyle {
         }
     }
 
-    fn line_points_triangle() -> impl Fn(
-        (f64, f64),
-        i32,
-        ShapeStyle,
-    ) -> ComposedElement<
-        (f64, f64),
-        SVGBackend<'static>,
-        TriangleMarker<(i32, i32), i32>,
-        Text<'static, (i32, i32), String>,
-    > {
+    fn line_points_triangle<'a>() -> impl Fn((f64, f64), i32, ShapeStyle) -> LabeledTriangle<'a> {
         |(x, y), s: i32, st: ShapeStyle| {
             // We want to construct a composed element on-the-fly
             EmptyElement::<(f64, f64), SVGBackend>::at((x, y))
@@ -87,12 +90,10 @@ impl CpuPlotStyle for BranchingStyle {
         Self::TOTAL_RGB.stroke_width(3)
     }
 
-    fn cpu_core_style() -> ShapeStyle {
-        Self::CORE_RGB.mix(0.8).stroke_width(2)
-    }
-
-    fn cpu_atom_style() -> ShapeStyle {
-        Self::ATOM_RGB.mix(0.5).stroke_width(1)
+    fn cpu_pmu_style(index: usize) -> ShapeStyle {
+        Self::PMU_RGBS[index % Self::PMU_RGBS.len()]
+            .mix(0.8)
+            .stroke_width(2)
     }
 
     fn branch_misses_style() -> ShapeStyle {
@@ -107,17 +108,16 @@ impl BranchingStyle {
     pub const TOTAL_POINT_SIZE: f32 = 4.;
     pub const TOTAL_LINE_WIDTH: f32 = 3.;
 
-    pub const CORE_LEGEND: &'static str = "Branching CORE";
-    pub const CORE_COLOR: &'static str = "#e5a73e";
-    pub const CORE_RGB: RGBColor = RGBColor(0xe5, 0xa7, 0x3e);
-    pub const CORE_POINT_SIZE: f32 = 2.5;
-    pub const CORE_LINE_WIDTH: f32 = 1.5;
-
-    pub const ATOM_LEGEND: &'static str = "Branching ATOM";
-    pub const ATOM_COLOR: &'static str = "#e4ca9d";
-    pub const ATOM_RGB: RGBColor = RGBColor(0xe4, 0xca, 0x9d);
-    pub const ATOM_POINT_SIZE: f32 = 2.;
-    pub const ATOM_LINE_WIDTH: f32 = 1.;
+    pub const PMU_LEGEND: &'static str = "Branching";
+    /// The colors of the core PMUs in the order they appear in the records, repeated if there are more PMUs
+    pub const PMU_COLORS: [&'static str; 3] = ["#e5a73e", "#e4ca9d", "#b36b00"];
+    pub const PMU_RGBS: [RGBColor; 3] = [
+        RGBColor(0xe5, 0xa7, 0x3e),
+        RGBColor(0xe4, 0xca, 0x9d),
+        RGBColor(0xb3, 0x6b, 0),
+    ];
+    pub const PMU_POINT_SIZE: f32 = 2.5;
+    pub const PMU_LINE_WIDTH: f32 = 1.5;
 
     pub const BRANCH_MISSES_COLOR: &'static str = "#d14419";
     pub const BRANCH_MISSES_RGB: RGBColor = RGBColor(0xd1, 0x44, 0x19);
@@ -129,38 +129,34 @@ impl BranchingStyle {
     pub fn color_cpu(cpu: Cpu) -> &'static str {
         match cpu {
             Cpu::Total => Self::TOTAL_COLOR,
-            Cpu::Core => Self::CORE_COLOR,
-            Cpu::Atom => Self::ATOM_COLOR,
+            Cpu::Pmu { index, .. } => Self::PMU_COLORS[index % Self::PMU_COLORS.len()],
         }
     }
 
     pub fn line_width_cpu(cpu: Cpu) -> f32 {
         match cpu {
             Cpu::Total => Self::TOTAL_LINE_WIDTH,
-            Cpu::Core => Self::CORE_LINE_WIDTH,
-            Cpu::Atom => Self::ATOM_LINE_WIDTH,
+            Cpu::Pmu { .. } => Self::PMU_LINE_WIDTH,
         }
     }
 
     pub fn point_size_cpu(cpu: Cpu) -> f32 {
         match cpu {
             Cpu::Total => Self::TOTAL_POINT_SIZE,
-            Cpu::Core => Self::CORE_POINT_SIZE,
-            Cpu::Atom => Self::ATOM_POINT_SIZE,
+            Cpu::Pmu { .. } => Self::PMU_POINT_SIZE,
         }
     }
 
-    pub fn legend_cpu(cpu: Cpu) -> &'static str {
+    pub fn legend_cpu(cpu: Cpu) -> String {
         match cpu {
-            Cpu::Total => Self::TOTAL_LEGEND,
-            Cpu::Core => Self::CORE_LEGEND,
-            Cpu::Atom => Self::ATOM_LEGEND,
+            Cpu::Total => Self::TOTAL_LEGEND.to_string(),
+            Cpu::Pmu { name, .. } => format!("{} {name}", Self::PMU_LEGEND),
         }
     }
 
     pub fn line_plot_cpu(val_over_x: Vec<(f64, f64)>, cpu: Cpu) -> Plot {
         Plot::new(val_over_x)
-            .legend(Self::legend_cpu(cpu).to_string())
+            .legend(Self::legend_cpu(cpu))
             .line_style(
                 LineStyle::new()
                     .colour(Self::color_cpu(cpu))
@@ -197,7 +193,7 @@ impl BranchingStyle {
             .legend("Branching: Duration [s]".to_string())
             .line_style(
                 LineStyle::new()
-                    .colour(Self::CORE_COLOR)
+                    .colour(Self::PMU_COLORS[0])
                     .width(2.)
                     .linejoin(LineJoin::Round),
             )
@@ -205,7 +201,7 @@ impl BranchingStyle {
                 PointStyle::new()
                     .marker(PointMarker::Circle)
                     .size(1.0)
-                    .colour(Self::CORE_COLOR),
+                    .colour(Self::PMU_COLORS[0]),
             )
     }
 }
@@ -217,12 +213,10 @@ impl CpuPlotStyle for BranchlessStyle {
         Self::TOTAL_RGB.stroke_width(3)
     }
 
-    fn cpu_core_style() -> ShapeStyle {
-        Self::CORE_RGB.mix(0.8).stroke_width(2)
-    }
-
-    fn cpu_atom_style() -> ShapeStyle {
-        Self::ATOM_RGB.mix(0.5).stroke_width(1)
+    fn cpu_pmu_style(index: usize) -> ShapeStyle {
+        Self::PMU_RGBS[index % Self::PMU_RGBS.len()]
+            .mix(0.8)
+            .stroke_width(2)
     }
 
     fn branch_misses_style() -> ShapeStyle {
@@ -237,18 +231,16 @@ impl BranchlessStyle {
     pub const TOTAL_POINT_SIZE: f32 = 4.;
     pub const TOTAL_LINE_WIDTH: f32 = 3.;
 
-    pub const CORE_LEGEND: &'static str = "Branchless CORE";
-    pub const CORE_COLOR: &'static str = "#1691ff";
-    pub const CORE_RGB: RGBColor = RGBColor(0x16, 0x91, 0xff);
-    pub const CORE_POINT_SIZE: f32 = 2.5;
-    pub const CORE_LINE_WIDTH: f32 = 1.5;
-
-    pub const ATOM_LEGEND: &'static str = "Branchless ATOM";
-    pub const ATOM_COLOR: &'static str = "#88bae7";
-    pub const ATOM_RGB: RGBColor = RGBColor(0x88, 0xba, 0xe7);
-
-    pub const ATOM_POINT_SIZE: f32 = 2.;
-    pub const ATOM_LINE_WIDTH: f32 = 1.;
+    pub const PMU_LEGEND: &'static str = "Branchless";
+    /// The colors of the core PMUs in the order they appear in the records, repeated if there are more PMUs
+    pub const PMU_COLORS: [&'static str; 3] = ["#1691ff", "#88bae7", "#01457f"];
+    pub const PMU_RGBS: [RGBColor; 3] = [
+        RGBColor(0x16, 0x91, 0xff),
+        RGBColor(0x88, 0xba, 0xe7),
+        RGBColor(0x01, 0x45, 0x7f),
+    ];
+    pub const PMU_POINT_SIZE: f32 = 2.5;
+    pub const PMU_LINE_WIDTH: f32 = 1.5;
 
     pub const BRANCH_MISSES_COLOR: &'static str = "#5d00d1";
     pub const BRANCH_MISSES_RGB: RGBColor = RGBColor(0x5d, 0x00, 0xd1);
@@ -256,38 +248,34 @@ impl BranchlessStyle {
     pub fn color_cpu(cpu: Cpu) -> &'static str {
         match cpu {
             Cpu::Total => Self::TOTAL_COLOR,
-            Cpu::Core => Self::CORE_COLOR,
-            Cpu::Atom => Self::ATOM_COLOR,
+            Cpu::Pmu { index, .. } => Self::PMU_COLORS[index % Self::PMU_COLORS.len()],
         }
     }
 
     pub fn line_width_cpu(cpu: Cpu) -> f32 {
         match cpu {
             Cpu::Total => Self::TOTAL_LINE_WIDTH,
-            Cpu::Core => Self::CORE_LINE_WIDTH,
-            Cpu::Atom => Self::ATOM_LINE_WIDTH,
+            Cpu::Pmu { .. } => Self::PMU_LINE_WIDTH,
         }
     }
 
     pub fn point_size_cpu(cpu: Cpu) -> f32 {
         match cpu {
             Cpu::Total => Self::TOTAL_POINT_SIZE,
-            Cpu::Core => Self::CORE_POINT_SIZE,
-            Cpu::Atom => Self::ATOM_POINT_SIZE,
+            Cpu::Pmu { .. } => Self::PMU_POINT_SIZE,
         }
     }
 
-    pub fn legend_cpu(cpu: Cpu) -> &'static str {
+    pub fn legend_cpu(cpu: Cpu) -> String {
         match cpu {
-            Cpu::Total => Self::TOTAL_LEGEND,
-            Cpu::Core => Self::CORE_LEGEND,
-            Cpu::Atom => Self::ATOM_LEGEND,
+            Cpu::Total => Self::TOTAL_LEGEND.to_string(),
+            Cpu::Pmu { name, .. } => format!("{} {name}", Self::PMU_LEGEND),
         }
     }
 
     pub fn line_plot_cpu(val_over_x: Vec<(f64, f64)>, cpu: Cpu) -> Plot {
         Plot::new(val_over_x)
-            .legend(Self::legend_cpu(cpu).to_string())
+            .legend(Self::legend_cpu(cpu))
             .line_style(
                 LineStyle::new()
                     .colour(Self::color_cpu(cpu))
@@ -324,7 +312,7 @@ impl BranchlessStyle {
             .legend("Branchless: Duration [s]".to_string())
             .line_style(
                 LineStyle::new()
-                    .colour(Self::CORE_COLOR)
+                    .colour(Self::PMU_COLORS[0])
                     .width(2.)
                     .linejoin(LineJoin::Round),
             )
@@ -332,49 +320,34 @@ impl BranchlessStyle {
                 PointStyle::new()
                     .marker(PointMarker::Circle)
                     .size(1.0)
-                    .colour(Self::CORE_COLOR),
+                    .colour(Self::PMU_COLORS[0]),
             )
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub struct PerfStatRecord {
-    counter_value: String,
-    unit: String,
-    event: String,
-    variance: Option<f32>,
-    event_runtime: u64,
-    pcnt_running: f32,
-    metric_value: String,
-    metric_unit: String,
-}
-
 pub fn read_perf_stat_record_json(
     in_branch_json: PathBuf,
     in_branchless_json: PathBuf,
 ) -> Result<(), Box<dyn Error>> {
-    let branching = std::fs::read_to_string(in_branch_json)?;
-    let branchless = std::fs::read_to_string(in_branchless_json)?;
-    let perf_stats_branching: Vec<PerfStatRecord> = serde_json::from_str(&branching)?;
-    let perf_stats_branchless: Vec<PerfStatRecord> = serde_json::from_str(&branchless)?;
+    let perf_stats_branching: Vec<PerfStatRecord> = read_perf_stat_file(&in_branch_json)?;
+    let perf_stats_branchless: Vec<PerfStatRecord> = read_perf_stat_file(&in_branchless_json)?;
 
-    let mut instructions_b_vs_bl: (u64, u64) = (0, 0);
+    // Summed over the PMUs that counted them, e.g. `cpu_core` and `cpu_atom` on a hybrid CPU
+    let instructions: Event = "instructions".parse()?;
+    let mut instructions_b_vs_bl: (f64, f64) = (0., 0.);
 
     for e in perf_stats_branching {
         println!("{e:?}");
-        if e.event == "cpu_core/instructions:u/" {
-            let trimmed_counter_value = e.counter_value.split('.').next().unwrap();
-            eprintln!("{trimmed_counter_value}");
-            instructions_b_vs_bl.0 = trimmed_counter_value.parse().unwrap();
+        if e.event.matches(&instructions) {
+            eprintln!("{}", e.counter_value);
+            instructions_b_vs_bl.0 += e.value_or_zero();
         }
     }
 
     for be in perf_stats_branchless {
-        if be.event == "cpu_core/instructions:u/" {
-            let trimmed_counter_value = be.counter_value.split('.').next().unwrap();
-            eprintln!("{trimmed_counter_value}");
-            instructions_b_vs_bl.1 = trimmed_counter_value.parse().unwrap();
+        if be.event.matches(&instructions) {
+            eprintln!("{}", be.counter_value);
+            instructions_b_vs_bl.1 += be.value_or_zero();
         }
     }
 
@@ -387,12 +360,12 @@ pub fn read_perf_stat_record_json(
     use plotlib::style::BoxStyle;
     use plotlib::view::CategoricalView;
 
-    let bar1 = BarChart::new(instructions_b_vs_bl.0 as f64)
+    let bar1 = BarChart::new(instructions_b_vs_bl.0)
         .label("Branching")
-        .style(&BoxStyle::new().fill(BranchingStyle::CORE_COLOR));
-    let bar2 = BarChart::new(instructions_b_vs_bl.1 as f64)
+        .style(&BoxStyle::new().fill(BranchingStyle::PMU_COLORS[0]));
+    let bar2 = BarChart::new(instructions_b_vs_bl.1)
         .label("Branchless")
-        .style(&BoxStyle::new().fill(BranchlessStyle::CORE_COLOR));
+        .style(&BoxStyle::new().fill(BranchlessStyle::PMU_COLORS[0]));
 
     let view = CategoricalView::new()
         .add(bar1)
@@ -443,6 +416,8 @@ impl Display for Magnitude {
 }
 
 impl Magnitude {
+    /// The magnitude of the number, [`Magnitude::Eminus9`] for anything smaller, e.g. the normalized counts of
+    /// rare events
     pub fn classify(num: f64) -> Self {
         if num == 0.0 {
             return Magnitude::E0;
@@ -462,8 +437,38 @@ impl Magnitude {
             0.. => Magnitude::E0,
             -3.. => Magnitude::Eminus3,
             -6.. => Magnitude::Eminus6,
-            -9.. => Magnitude::Eminus9,
-            _ => unreachable!("what kinda data is this?? num={num}"),
+            _ => Magnitude::Eminus9,
+        }
+    }
+
+    /// The magnitude to scale an axis by whose smallest value is `min`
+    ///
+    /// Fractions down to a thousandth, e.g. an IPC of 0.85, are plotted as they are, smaller values such as the
+    /// counts of rare events per iteration are scaled up.
+    pub fn of_axis(min: f64) -> Self {
+        if !min.is_finite() {
+            return Magnitude::E0;
+        }
+        match Magnitude::classify(min) {
+            Magnitude::Eminus3 => Magnitude::E0,
+            magnitude => magnitude,
+        }
+    }
+
+    /// How the values are scaled on an axis, e.g. `10 ^ 6`, empty if they aren't
+    pub fn axis_label(&self) -> String {
+        match self {
+            Magnitude::E3 => "10 ^ 3".to_string(),
+            Magnitude::E6 => "10 ^ 6".to_string(),
+            Magnitude::E9 => "10 ^ 9".to_string(),
+            Magnitude::E12 => "10 ^ 12".to_string(),
+            Magnitude::E15 | Magnitude::E18 | Magnitude::E21 | Magnitude::E24 | Magnitude::E27 => {
+                format!("{self}")
+            }
+            Magnitude::Eminus9 => "10 ^ -9".to_string(),
+            Magnitude::Eminus6 => "10 ^ -6".to_string(),
+            Magnitude::Eminus3 => "10 ^ -3".to_string(),
+            Magnitude::E0 => String::new(),
         }
     }
 
@@ -491,411 +496,1275 @@ pub fn perf_stats_from_json_files(
 ) -> Result<Vec<Vec<PerfStatRecord>>, Box<dyn Error>> {
     let mut vec_perf = vec![];
     for f in files {
-        let s = std::fs::read_to_string(f)?;
-        let perf_data = serde_json::from_str(&s)?;
-        vec_perf.push(perf_data);
+        vec_perf.push(read_perf_stat_file(f)?);
     }
     Ok(vec_perf)
 }
 
-pub fn vals_from_perf_stats(
-    perf_stat_runs: &[Vec<PerfStatRecord>],
-    event: &str,
-) -> Result<(Vec<f64>, Magnitude, u64), Box<dyn Error>> {
-    let mut vals: Vec<u64> = vec![];
-    for perf_stats in perf_stat_runs {
-        for data in perf_stats {
-            if data.event.contains(event) {
-                if data.counter_value == "<not counted>" {
-                    vals.push(0);
-                } else {
-                    let v = data.counter_value.split('.').next().unwrap();
-                    vals.push(v.parse()?);
-                }
+/// Mean and standard error of a record of `perf stat --repeat <runs>`, 0 if it wasn't counted
+fn estimate(record: &PerfStatRecord, runs: u32) -> Estimate {
+    Estimate::from_record(record, runs).unwrap_or(Estimate::new(0., 0., 0))
+}
+
+/// The means of a series, to draw the line through
+fn means(series: &[(f64, Estimate)]) -> Vec<(f64, f64)> {
+    series.iter().map(|(x, e)| (*x, e.mean)).collect()
+}
+
+/// The largest upper bound of the confidence intervals in the series
+fn max_upper_bound<'a>(series: impl IntoIterator<Item = &'a (f64, Estimate)>) -> f64 {
+    series
+        .into_iter()
+        .map(|(_, e)| e.ci95().1)
+        .fold(0.0, f64::max)
+}
+
+/// Draw the 95% confidence interval of every point in the series
+fn draw_error_bars(
+    chart: &mut XyChart<'_, '_>,
+    series: &[(f64, Estimate)],
+    style: impl Into<ShapeStyle>,
+) -> Result<(), Box<dyn Error>> {
+    let style: ShapeStyle = style.into();
+    chart.draw_series(series.iter().map(|(x, e)| {
+        let (lower, upper) = e.ci95();
+        ErrorBar::new_vertical(*x, lower, e.mean, upper, style.stroke_width(1), 8)
+    }))?;
+    Ok(())
+}
+
+/// Explain the error bars in the legend of the chart, see [`PlotOptions::error_bars_label`]
+fn draw_error_bars_legend(
+    chart: &mut XyChart<'_, '_>,
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let style = BLACK.stroke_width(1);
+    chart
+        .draw_series(std::iter::empty::<PathElement<(f64, f64)>>())?
+        .label(options.error_bars_label())
+        .legend(move |(x, y)| ErrorBar::new_vertical(x + 10, y - 6, y, y + 6, style, 8));
+    Ok(())
+}
+
+/// The names of the two kernels in a plot, used in the titles and legends
+#[derive(Debug, Clone, Copy)]
+pub struct KernelNames<'a> {
+    pub baseline: &'a str,
+    pub candidate: &'a str,
+}
+
+/// What the plots over x show and how, the same for all the plots of a sweep
+#[derive(Debug, Clone, Copy)]
+pub struct PlotOptions<'a> {
+    pub names: &'a KernelNames<'a>,
+    pub x_axis: &'a XAxis,
+    pub coverage: &'a Coverage,
+    pub normalize: Normalize,
+    /// The `--repeat` count of the runs at every x, for the confidence intervals, `None` where it isn't known
+    pub repeats: &'a [Option<u32>],
+}
+
+impl PlotOptions<'_> {
+    /// The `--repeat` count of the runs at the x with this index, 0 if it isn't known
+    fn repeat_at(&self, index: usize) -> u32 {
+        self.repeats.get(index).copied().flatten().unwrap_or(0)
+    }
+
+    /// What the error bars show, the 95% confidence interval if the number of runs is known
+    fn error_bars_label(&self) -> String {
+        let repeats: Option<Vec<u32>> = self.repeats.iter().copied().collect();
+        match repeats.as_deref() {
+            Some([repeat, rest @ ..]) if rest.iter().all(|r| r == repeat) => {
+                format!("95% CI of the mean of {repeat} runs")
+            }
+            Some([_, ..]) => String::from("95% CI of the mean"),
+            _ => String::from("±1.96 standard errors"),
+        }
+    }
+}
+
+/// The x-axis of the plots over x, e.g. the ratio of true values or the number of elements
+///
+/// A log-scaled axis is drawn over `log10(x)` with the labels showing x, so the charts keep their linear coordinates.
+#[derive(Debug, Clone)]
+pub struct XAxis {
+    /// What's on the axis, e.g. `True/False ratio`
+    pub name: String,
+    pub unit: Option<String>,
+    pub log: bool,
+    /// x values marked with a labelled vertical line, e.g. the sizes of the caches
+    pub markers: Vec<(String, f64)>,
+    /// The names of the values if they're categories rather than numbers, e.g. the element types, which are then
+    /// plotted at the index of their name
+    pub categories: Vec<String>,
+}
+
+impl XAxis {
+    /// The axis description with the unit, e.g. `True/False ratio [%]`
+    fn label(&self) -> String {
+        match &self.unit {
+            Some(unit) => format!("{} [{unit}]", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Draw the markers as vertical lines across the chart, labelled at the top
+    fn draw_markers(&self, chart: &mut XyChart<'_, '_>) -> Result<(), Box<dyn Error>> {
+        let (x_range, y_range) = (chart.x_range(), chart.y_range());
+        for (label, x) in &self.markers {
+            let Ok(x) = self.positions(&[*x]).map(|p| p[0]) else {
+                continue;
+            };
+            if !x_range.contains(&x) {
+                continue;
             }
+            let style = BLACK.mix(0.5).stroke_width(1);
+            chart.draw_series(std::iter::once(PathElement::new(
+                vec![(x, y_range.start), (x, y_range.end)],
+                style,
+            )))?;
+            chart.draw_series(std::iter::once(
+                EmptyElement::at((x, y_range.end))
+                    + Text::new(label.clone(), (4, 4), ("Arial", 14).into_font()),
+            ))?;
         }
+        Ok(())
     }
 
-    let min: u64 = *vals.iter().min().unwrap();
-    let max: u64 = *vals.iter().max().unwrap();
+    /// Where the x values are drawn on the charts
+    fn positions(&self, x_vals: &[f64]) -> Result<Vec<f64>, Box<dyn Error>> {
+        if !self.log {
+            return Ok(x_vals.to_vec());
+        }
+        match x_vals.iter().find(|x| **x <= 0.) {
+            Some(x) => Err(format!(
+                "Can't put {x} on a log-scaled x-axis, the x values have to be positive"
+            )
+            .into()),
+            None => Ok(x_vals.iter().map(|x| x.log10()).collect()),
+        }
+    }
 
-    let float_vals = vals.into_iter().map(|x| x as f64).collect();
-    Ok((float_vals, Magnitude::classify(min as f64), max))
+    /// The range of the charts covering the positions with a margin
+    fn range(&self, positions: &[f64]) -> Range<f64> {
+        let min = positions.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = positions.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if !min.is_finite() || !max.is_finite() {
+            return 0.0..1.0;
+        }
+        let margin = if !self.categories.is_empty() {
+            0.5
+        } else if max > min {
+            (max - min) * 0.03
+        } else {
+            1.
+        };
+        let start = if !self.log && min >= 0. {
+            // A linear axis of non-negative values starts at zero at the latest
+            (min - margin).max(0.)
+        } else {
+            min - margin
+        };
+        start..max + margin
+    }
+
+    /// The label of a position on the charts with three significant digits, e.g. `0.125`, `3.16` or `31600`
+    ///
+    /// Categories are only labelled at their own positions.
+    fn format_label(&self, position: f64) -> String {
+        if !self.categories.is_empty() {
+            return match self.categories.get(position as usize) {
+                Some(name) if position.fract() == 0. => name.clone(),
+                _ => String::new(),
+            };
+        }
+        let x = if self.log {
+            10f64.powf(position)
+        } else {
+            position
+        };
+        if x == 0. || !x.is_finite() {
+            return format!("{x}");
+        }
+        let digits_before_point = x.abs().log10().floor() as i32 + 1;
+        if digits_before_point > 3 {
+            let unit = 10f64.powi(digits_before_point - 3);
+            return format!("{}", (x / unit).round() * unit);
+        }
+        let decimals = (3 - digits_before_point) as usize;
+        let label = format!("{x:.decimals$}");
+        if label.contains('.') {
+            label
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        } else {
+            label
+        }
+    }
 }
 
 pub fn plot_vs_x(
-    x_vals: Vec<u64>,
+    x_vals: Vec<f64>,
     branching_files: Vec<PathBuf>,
     branchless_files: Vec<PathBuf>,
-    save_to: &'static OsStr,
+    elements: &[Option<usize>],
+    options: &PlotOptions,
+    save_to: &OsStr,
     plot_type: PlotType,
 ) -> Result<(), Box<dyn Error>> {
-    let ratio_vals: Vec<f64> = x_vals.into_iter().map(|x| x as f64).collect();
-
     let br_perf_stats: Vec<Vec<PerfStatRecord>> = perf_stats_from_json_files(&branching_files)?;
     let bl_perf_stats: Vec<Vec<PerfStatRecord>> = perf_stats_from_json_files(&branchless_files)?;
 
+    plot_perf_stats_vs_x(
+        x_vals,
+        &br_perf_stats,
+        &bl_perf_stats,
+        elements,
+        options,
+        save_to,
+        plot_type,
+    )
+}
+
+/// Same as [`plot_vs_x`] but for perf stat records that are already in memory
+///
+/// `elements` is the number of elements of the runs at every x, needed to normalize per element or iteration.
+pub fn plot_perf_stats_vs_x(
+    x_vals: Vec<f64>,
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    elements: &[Option<usize>],
+    options: &PlotOptions,
+    save_to: &OsStr,
+    plot_type: PlotType,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions {
+        x_axis, normalize, ..
+    } = *options;
+    let x_vals = x_axis.positions(&x_vals)?;
+    let br_perf_stats = &normalize.records(&without_setup(br_perf_stats), elements)?;
+    let bl_perf_stats = &normalize.records(&without_setup(bl_perf_stats), elements)?;
+
     match plot_type {
         PlotType::CpuInstructions => {
-            cpu_instructions_plot_view(save_to, ratio_vals, &br_perf_stats, &bl_perf_stats)?;
+            cpu_instructions_plot_view(save_to, x_vals, br_perf_stats, bl_perf_stats, options)?;
         }
         PlotType::TimeBranchMisses => {
-            time_branch_misses_plot_view(save_to, ratio_vals, &br_perf_stats, &bl_perf_stats)?;
+            time_branch_misses_plot_view(save_to, x_vals, br_perf_stats, bl_perf_stats, options)?;
         }
         PlotType::Merged => {
-            todo!("");
+            merged_plot_view(save_to, x_vals, br_perf_stats, bl_perf_stats, options)?;
         }
     }
 
     Ok(())
 }
 
-fn time_branch_misses_plot_view(
-    save_to: &'static OsStr,
-    ratio_vals: Vec<f64>,
+/// Plot each of the events over x in its own panel, stacked so they share the x-axis
+///
+/// An event without a PMU, e.g. `instructions`, gets a line for every PMU that counted it, `cpu_core/instructions`
+/// only the one for `cpu_core`.
+pub fn plot_events_vs_x(
+    x_vals: Vec<f64>,
     br_perf_stats: &[Vec<PerfStatRecord>],
     bl_perf_stats: &[Vec<PerfStatRecord>],
+    elements: &[Option<usize>],
+    options: &PlotOptions,
+    events: &[Event],
+    save_to: &OsStr,
 ) -> Result<(), Box<dyn Error>> {
-    let (br_durations_x, bl_durations_x) =
-        branching_branchless_durations_over_x(ratio_vals.clone(), br_perf_stats, bl_perf_stats)?;
+    let PlotOptions {
+        names,
+        x_axis,
+        normalize,
+        ..
+    } = *options;
+    let x_vals = x_axis.positions(&x_vals)?;
+    let br_perf_stats = &normalize.records(&without_setup(br_perf_stats), elements)?;
+    let bl_perf_stats = &normalize.records(&without_setup(bl_perf_stats), elements)?;
+
+    let available = distinct_events(br_perf_stats.iter().chain(bl_perf_stats).flatten());
+    if let Some(missing) = events
+        .iter()
+        .find(|query| !available.iter().any(|event| event.matches(query)))
+    {
+        let available: Vec<String> = available.iter().map(Event::to_string).collect();
+        return Err(format!(
+            "No records of {missing}, the events in the JSON files are: {}",
+            available.join(", ")
+        )
+        .into());
+    }
+
+    let root_drawing_area =
+        SVGBackend::new(save_to, (1024, 640 * events.len() as u32)).into_drawing_area();
 
-    let br_frac_misses_core = frac_branch_misses_core_from_perf_stats(br_perf_stats)?;
-    let bl_frac_misses_core = frac_branch_misses_core_from_perf_stats(bl_perf_stats)?;
+    root_drawing_area.fill(&WHITE).unwrap();
+    let root_drawing_area = root_drawing_area.titled(
+        &format!("{} vs. {}", names.baseline, names.candidate),
+        ("Arial", 40),
+    )?;
+
+    let panels = root_drawing_area.split_evenly((events.len(), 1));
+    for (panel, query) in panels.iter().zip(events) {
+        draw_event_chart(panel, query, &x_vals, br_perf_stats, bl_perf_stats, options)?;
+    }
+
+    Ok(())
+}
 
-    let br_frac_misses_x: Vec<(f64, f64)> = ratio_vals
-        .clone()
+/// The values of exactly `event` at every x it was counted at and isn't excluded for its coverage
+fn event_over_x(
+    x_vals: &[f64],
+    perf_stat_runs: &[Vec<PerfStatRecord>],
+    event: &Event,
+    options: &PlotOptions,
+) -> (SeriesOverX, Extrapolated) {
+    sum_over_x(x_vals, perf_stat_runs, options, |r| {
+        r.event == *event && options.coverage.is_plotted(r)
+    })
+}
+
+/// The values of the event summed over the PMUs that counted it with enough coverage, at every x where one did
+fn total_over_x(
+    x_vals: &[f64],
+    perf_stat_runs: &[Vec<PerfStatRecord>],
+    query: &Event,
+    options: &PlotOptions,
+) -> (SeriesOverX, Extrapolated) {
+    sum_over_x(x_vals, perf_stat_runs, options, |r| {
+        r.event.matches(query) && options.coverage.is_summed(r)
+    })
+}
+
+/// The sum of the counted records to include at every x where there are any, along with the points perf
+/// extrapolated, see [`PerfStatRecord::is_extrapolated`]
+fn sum_over_x(
+    x_vals: &[f64],
+    perf_stat_runs: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+    include: impl Fn(&PerfStatRecord) -> bool,
+) -> (SeriesOverX, Extrapolated) {
+    let mut series = vec![];
+    let mut extrapolated = vec![];
+    for (i, (x, records)) in x_vals.iter().zip(perf_stat_runs).enumerate() {
+        let counted: Vec<(&PerfStatRecord, Estimate)> = records
+            .iter()
+            .filter(|r| include(r))
+            .filter_map(|r| Some((r, Estimate::from_record(r, options.repeat_at(i))?)))
+            .collect();
+        let Some(sum) = counted.iter().map(|(_, e)| *e).reduce(|a, b| a + b) else {
+            continue;
+        };
+        series.push((*x, sum));
+        if counted.iter().any(|(r, _)| r.is_extrapolated()) {
+            let pcnt_running = counted
+                .iter()
+                .map(|(r, _)| r.pcnt_running)
+                .fold(100., f32::min);
+            extrapolated.push((*x, pcnt_running));
+        }
+    }
+    (series, extrapolated)
+}
+
+impl Coverage {
+    /// Whether perf ran the counter for less of the time than the threshold
+    pub fn is_low(&self, record: &PerfStatRecord) -> bool {
+        record.pcnt_running < self.min_pcnt_running
+    }
+
+    /// Whether the record is plotted on the line of its event
+    fn is_plotted(&self, record: &PerfStatRecord) -> bool {
+        self.low_coverage != LowCoverage::Exclude || !self.is_low(record)
+    }
+
+    /// Whether the record counts towards the totals over the PMUs
+    fn is_summed(&self, record: &PerfStatRecord) -> bool {
+        self.low_coverage == LowCoverage::Keep || !self.is_low(record)
+    }
+
+    /// The style of a line, faded if it's de-emphasized for points with low coverage
+    fn line_style(&self, style: ShapeStyle, extrapolated: &Extrapolated) -> ShapeStyle {
+        let is_low = extrapolated
+            .iter()
+            .any(|(_, pcnt_running)| *pcnt_running < self.min_pcnt_running);
+        if self.low_coverage == LowCoverage::DeEmphasize && is_low {
+            ShapeStyle {
+                color: style.color.mix(0.3),
+                filled: style.filled,
+                stroke_width: 1,
+            }
+        } else {
+            style
+        }
+    }
+}
+
+/// Print a warning for every event perf counted for less of the time than the threshold at any x
+pub fn warn_low_coverage(
+    kernel: &str,
+    perf_stat_runs: &[Vec<PerfStatRecord>],
+    coverage: &Coverage,
+) {
+    let low_records = || {
+        perf_stat_runs
+            .iter()
+            .flatten()
+            .filter(|r| !r.is_setup() && r.counter_value.counted().is_some() && coverage.is_low(r))
+    };
+    let treatment = match coverage.low_coverage {
+        LowCoverage::Keep => "plotted like the others",
+        LowCoverage::DeEmphasize => "faded and left out of the totals",
+        LowCoverage::Exclude => "left out of the plots",
+    };
+    for event in distinct_events(low_records()) {
+        let pcnt_running = low_records()
+            .filter(|r| r.event == event)
+            .map(|r| r.pcnt_running)
+            .fold(100., f32::min);
+        println!(
+            "Warning: perf counted {event} of the {kernel} kernel for as little as {pcnt_running:.2}% of the time, below --min-pcnt-running {}%. The extrapolated counts are {treatment}",
+            coverage.min_pcnt_running
+        );
+    }
+}
+
+impl Normalize {
+    /// Whether the values of the event are normalized, the metrics are ratios already and the duration per second
+    /// would always be 1
+    fn normalizes(&self, event: &Event) -> bool {
+        match self {
+            Normalize::None => false,
+            Normalize::PerSecond if event.name == "duration_time" => false,
+            _ => event.name.parse::<Metric>().is_err(),
+        }
+    }
+
+    /// What the values of the event are per, e.g. ` per element`, empty if they aren't normalized
+    fn per(&self, event: &Event) -> &'static str {
+        if !self.normalizes(event) {
+            return "";
+        }
+        match self {
+            Normalize::None => "",
+            Normalize::PerElement => " per element",
+            Normalize::PerIteration => " per iteration",
+            Normalize::PerSecond => " per second",
+        }
+    }
+
+    /// The unit of the durations in the plots and the factor from ns, which are too small for seconds per element
+    fn duration_unit(&self) -> (&'static str, f64) {
+        match self {
+            Normalize::None | Normalize::PerSecond => ("s", 1. / 1_000_000_000.),
+            Normalize::PerElement | Normalize::PerIteration => ("ns", 1.),
+        }
+    }
+
+    /// The y-axis description of the durations, e.g. `Duration per element [ns]`
+    fn duration_label(&self) -> String {
+        let per = match self {
+            Normalize::None | Normalize::PerSecond => "",
+            Normalize::PerElement => " per element",
+            Normalize::PerIteration => " per iteration",
+        };
+        format!("Duration{per} [{}]", self.duration_unit().0)
+    }
+
+    /// The records of every run with the counted values divided by what they're normalized to
+    ///
+    /// `elements` is the number of elements of the runs, needed per element and per iteration.
+    fn records(
+        &self,
+        perf_stat_runs: &[Vec<PerfStatRecord>],
+        elements: &[Option<usize>],
+    ) -> Result<Vec<Vec<PerfStatRecord>>, Box<dyn Error>> {
+        let duration_time: Event = "duration_time".parse()?;
+        let mut normalized = vec![];
+        for (i, records) in perf_stat_runs.iter().enumerate() {
+            let divisor = match self {
+                Normalize::None => return Ok(perf_stat_runs.to_vec()),
+                Normalize::PerElement | Normalize::PerIteration => {
+                    let n = elements.get(i).copied().flatten().ok_or(
+                        "Normalizing per element or iteration needs the number of elements of every run from the run manifests",
+                    )?;
+                    let passes = if *self == Normalize::PerIteration {
+                        PASSES
+                    } else {
+                        1
+                    };
+                    Estimate::new((n * passes) as f64, 0., 0)
+                }
+                Normalize::PerSecond => records
+                    .iter()
+                    .find(|r| r.event.matches(&duration_time))
+                    .and_then(|r| Estimate::from_record(r, 0))
+                    .ok_or("Normalizing per second needs the duration_time of every run")?
+                    .scale(1. / 1_000_000_000.),
+            };
+            normalized.push(
+                records
+                    .iter()
+                    .map(|record| match Estimate::from_record(record, 0) {
+                        Some(value) if self.normalizes(&record.event) => {
+                            let value = value.ratio(&divisor);
+                            PerfStatRecord {
+                                counter_value: CounterValue::Counted(value.mean),
+                                variance: Some(value.rel_sem_pct() as f32),
+                                ..record.clone()
+                            }
+                        }
+                        _ => record.clone(),
+                    })
+                    .collect(),
+            );
+        }
+        Ok(normalized)
+    }
+}
+
+/// The points of the series perf extrapolated, with the lowest percentage of the time a counter of the point ran
+fn extrapolated_points(series: &SeriesOverX, extrapolated: &Extrapolated) -> Vec<(f64, f64, f32)> {
+    extrapolated
+        .iter()
+        .filter_map(|(x, pcnt_running)| {
+            let (_, e) = series.iter().find(|(series_x, _)| series_x == x)?;
+            Some((*x, e.mean, *pcnt_running))
+        })
+        .collect()
+}
+
+/// Circle the extrapolated points and label them with the percentage of the time counted, with one legend entry
+fn draw_extrapolated_points(
+    chart: &mut XyChart<'_, '_>,
+    points: &[(f64, f64, f32)],
+) -> Result<(), Box<dyn Error>> {
+    if points.is_empty() {
+        return Ok(());
+    }
+    let style = BLACK.stroke_width(1);
+    chart
+        .draw_series(points.iter().map(|&(x, y, pcnt_running)| {
+            EmptyElement::at((x, y))
+                + Circle::new((0, 0), 9, style)
+                + Text::new(
+                    format!("{pcnt_running:.2}% counted"),
+                    (10, 10),
+                    ("sans-serif", 10).into_font(),
+                )
+        }))?
+        .label("Extrapolated by perf")
+        .legend(move |(x, y)| Circle::new((x + 10, y), 5, style));
+    Ok(())
+}
+
+/// Draw a line through the means of the series, labelled for the legend, with a marker and error bar at every point
+fn draw_series_with_points<'a, E>(
+    chart: &mut XyChart<'_, 'a>,
+    series: &SeriesOverX,
+    point_size: i32,
+    style: ShapeStyle,
+    label: String,
+    marker: impl Fn((f64, f64), i32, ShapeStyle) -> E,
+) -> Result<(), Box<dyn Error>>
+where
+    E: Drawable<SVGBackend<'a>>,
+    for<'b> &'b E: PointCollection<'b, (f64, f64)>,
+{
+    // Outside of the where clause, which the compiler would otherwise pick for the elements of the line too
+    fn draw_line(
+        chart: &mut XyChart<'_, '_>,
+        series: &SeriesOverX,
+        style: ShapeStyle,
+        label: String,
+    ) -> Result<(), Box<dyn Error>> {
+        chart
+            .draw_series(LineSeries::new(means(series), style))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        Ok(())
+    }
+
+    draw_line(chart, series, style, label)?;
+    chart.draw_series(PointSeries::of_element(
+        means(series),
+        point_size,
+        style,
+        &marker,
+    ))?;
+    draw_error_bars(chart, series, style)
+}
+
+/// A line per kernel and PMU that counted the event, the PMUs in different colors and the kernels with different
+/// markers and line widths
+fn draw_event_chart(
+    drawing_area: &PlotArea<'_>,
+    query: &Event,
+    x_vals: &[f64],
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions {
+        names,
+        x_axis,
+        coverage,
+        ..
+    } = *options;
+    let x_range = x_axis.range(x_vals);
+    let records = || br_perf_stats.iter().chain(bl_perf_stats).flatten();
+    let events: Vec<Event> = distinct_events(records())
         .into_iter()
-        .zip(br_frac_misses_core)
+        .filter(|event| event.matches(query))
         .collect();
-    let bl_frac_misses_x: Vec<(f64, f64)> = ratio_vals
-        .clone()
+    let unit = records()
+        .find(|r| r.event.matches(query))
+        .map(|r| r.unit.clone())
+        .unwrap_or_default();
+
+    type EventSeries<'e> = (
+        &'e Event,
+        (SeriesOverX, Extrapolated),
+        (SeriesOverX, Extrapolated),
+    );
+    let series: Vec<EventSeries> = events
+        .iter()
+        .map(|event| {
+            (
+                event,
+                event_over_x(x_vals, br_perf_stats, event, options),
+                event_over_x(x_vals, bl_perf_stats, event, options),
+            )
+        })
+        .collect();
+    let all_points = || {
+        series
+            .iter()
+            .flat_map(|(_, (br, _), (bl, _))| br.iter().chain(bl.iter()))
+    };
+    let min = all_points()
+        .map(|(_, e)| e.mean)
+        .fold(f64::INFINITY, f64::min);
+    let magnitude = Magnitude::of_axis(min);
+    let scale = 1. / magnitude.scale();
+    let max_scaled = max_upper_bound(all_points()) * scale;
+
+    let y_unit: Vec<String> = [magnitude.axis_label(), unit]
         .into_iter()
-        .zip(bl_frac_misses_core)
+        .filter(|s| !s.is_empty())
         .collect();
+    let name = format!("{query}{}", options.normalize.per(query));
+    let y_desc = if y_unit.is_empty() {
+        name
+    } else {
+        format!("{name} [{}]", y_unit.join(" "))
+    };
+
+    let mut chart = ChartBuilder::on(drawing_area)
+        .caption(query.to_string(), ("Arial", 30))
+        .set_label_area_size(LabelAreaPosition::Left, 40)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(x_range, 0.0..(max_scaled * 1.1).max(1.0))?;
+
+    chart
+        .configure_mesh()
+        .x_labels(10)
+        .y_desc(y_desc)
+        .y_labels(10)
+        .x_desc(x_axis.label())
+        .x_label_formatter(&|x| x_axis.format_label(*x))
+        .draw()?;
+    x_axis.draw_markers(&mut chart)?;
+
+    let mut extrapolated = vec![];
+    for (i, (event, (br_x, br_extrapolated), (bl_x, bl_extrapolated))) in series.iter().enumerate()
+    {
+        let color = Palette99::pick(i);
+        let scaled = |series: &SeriesOverX| -> SeriesOverX {
+            series.iter().map(|(x, e)| (*x, e.scale(scale))).collect()
+        };
+        // Excluded for their coverage at every x
+        if !br_x.is_empty() {
+            let br_x = scaled(br_x);
+            draw_series_with_points(
+                &mut chart,
+                &br_x,
+                4,
+                coverage.line_style(color.stroke_width(3), br_extrapolated),
+                format!("{}: {event}", names.baseline),
+                BranchingStyle::line_points_circle(),
+            )?;
+            extrapolated.extend(extrapolated_points(&br_x, br_extrapolated));
+        }
+        if !bl_x.is_empty() {
+            let bl_x = scaled(bl_x);
+            draw_series_with_points(
+                &mut chart,
+                &bl_x,
+                5,
+                coverage.line_style(color.stroke_width(1), bl_extrapolated),
+                format!("{}: {event}", names.candidate),
+                BranchlessStyle::line_points_triangle(),
+            )?;
+            extrapolated.extend(extrapolated_points(&bl_x, bl_extrapolated));
+        }
+    }
+    draw_extrapolated_points(&mut chart, &extrapolated)?;
+
+    draw_error_bars_legend(&mut chart, options)?;
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
+
+    Ok(())
+}
+
+/// The records of the kernel or the whole process, the plots don't show the setup
+fn without_setup(perf_stat_runs: &[Vec<PerfStatRecord>]) -> Vec<Vec<PerfStatRecord>> {
+    perf_stat_runs
+        .iter()
+        .map(|records| records.iter().filter(|r| !r.is_setup()).cloned().collect())
+        .collect()
+}
+
+fn time_branch_misses_plot_view(
+    save_to: &OsStr,
+    x_vals: Vec<f64>,
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions { names, x_axis, .. } = *options;
+    let x_range = x_axis.range(&x_vals);
+    let (br_durations_x, bl_durations_x) = branching_branchless_durations_over_x(
+        x_vals.clone(),
+        br_perf_stats,
+        bl_perf_stats,
+        options,
+    )?;
+    let max_duration = max_upper_bound(br_durations_x.iter().chain(&bl_durations_x));
+
+    let (br_frac_misses_x, br_extrapolated) =
+        frac_branch_misses_over_x(&x_vals, br_perf_stats, options)?;
+    let (bl_frac_misses_x, bl_extrapolated) =
+        frac_branch_misses_over_x(&x_vals, bl_perf_stats, options)?;
 
     let root_drawing_area = SVGBackend::new(save_to, (1024, 768)).into_drawing_area();
 
     root_drawing_area.fill(&WHITE).unwrap();
-    
+
     let mut chart = ChartBuilder::on(&root_drawing_area)
         .caption("Duration vs. Branch Misses", ("Arial", 30))
         .set_label_area_size(LabelAreaPosition::Left, 40)
         .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .build_cartesian_2d(0.0..103.0, 0.0..5.0)?;
+        .build_cartesian_2d(x_range, 0.0..(max_duration * 1.1).max(5.0))?;
 
     chart
         .configure_mesh()
         .x_labels(10)
-        .y_desc("Branch misses [%] / Duration [s]")
+        .y_desc(format!(
+            "Branch misses [%] / {}",
+            options.normalize.duration_label()
+        ))
         .y_labels(10)
-        .x_desc("True/False ratio [%]")
+        .x_desc(x_axis.label())
+        .x_label_formatter(&|x| x_axis.format_label(*x))
         .draw()?;
+    x_axis.draw_markers(&mut chart)?;
 
-        chart
-        .draw_series(LineSeries::new(
-            br_frac_misses_x.clone(),
-            RED,
-        ))?
-        .label("Branching: Branch Misses")
+    chart
+        .draw_series(LineSeries::new(means(&br_frac_misses_x), RED))?
+        .label(format!("{}: Branch Misses", names.baseline))
         .legend(BranchingStyle::branch_misses_legend_style());
-        chart.draw_series(PointSeries::of_element(
-            br_frac_misses_x.clone(),
-            3,
-            RED,
-            &BranchingStyle::line_points_circle(),
-        ))?;
+    chart.draw_series(PointSeries::of_element(
+        means(&br_frac_misses_x),
+        3,
+        RED,
+        &BranchingStyle::line_points_circle(),
+    ))?;
+    draw_error_bars(&mut chart, &br_frac_misses_x, RED)?;
 
-        chart
-        .draw_series(LineSeries::new(
-            bl_frac_misses_x.clone(),
-            PURPLE,
-        ))?
-        .label("Branchless: Branch Misses")
+    chart
+        .draw_series(LineSeries::new(means(&bl_frac_misses_x), PURPLE))?
+        .label(format!("{}: Branch Misses", names.candidate))
         .legend(BranchingStyle::branch_misses_legend_style());
-        chart.draw_series(PointSeries::of_element(
-            bl_frac_misses_x.clone(),
-            3,
-            PURPLE,
-            &BranchingStyle::line_points_triangle(),
-        ))?;
+    chart.draw_series(PointSeries::of_element(
+        means(&bl_frac_misses_x),
+        3,
+        PURPLE,
+        &BranchingStyle::line_points_triangle(),
+    ))?;
+    draw_error_bars(&mut chart, &bl_frac_misses_x, PURPLE)?;
+    draw_extrapolated_points(
+        &mut chart,
+        &[
+            extrapolated_points(&br_frac_misses_x, &br_extrapolated),
+            extrapolated_points(&bl_frac_misses_x, &bl_extrapolated),
+        ]
+        .concat(),
+    )?;
+
+    draw_durations(&mut chart, &br_durations_x, &bl_durations_x, names)?;
+
+    draw_error_bars_legend(&mut chart, options)?;
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::MiddleRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
 
+    Ok(())
+}
 
-        chart
-        .draw_series(LineSeries::new(
-            br_durations_x.clone(),
-            BranchingStyle::cpu_total_style(),
-        ))?
-        .label("Branching: Duration")
-        .legend(BranchingStyle::cpu_total_legend_style());
-        chart.draw_series(PointSeries::of_element(
-            br_durations_x.clone(),
-            0,
-            BranchingStyle::cpu_total_style(),
-            &BranchingStyle::line_points_circle(),
-        ))?;
+/// One SVG with a panel per measurement, stacked so they share the x-axis
+fn merged_plot_view(
+    save_to: &OsStr,
+    x_vals: Vec<f64>,
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions { names, .. } = *options;
+    let root_drawing_area = SVGBackend::new(save_to, (1024, 1920)).into_drawing_area();
 
+    root_drawing_area.fill(&WHITE).unwrap();
+    let root_drawing_area = root_drawing_area.titled(
+        &format!("{} vs. {}", names.baseline, names.candidate),
+        ("Arial", 40),
+    )?;
+
+    let panels = root_drawing_area.split_evenly((3, 1));
+
+    draw_cpu_instructions_chart(
+        &panels[0],
+        "CPU Instructions",
+        x_vals.clone(),
+        br_perf_stats,
+        bl_perf_stats,
+        options,
+    )?;
+    draw_durations_chart(
+        &panels[1],
+        x_vals.clone(),
+        br_perf_stats,
+        bl_perf_stats,
+        options,
+    )?;
+    draw_branch_misses_chart(&panels[2], x_vals, br_perf_stats, bl_perf_stats, options)?;
 
-        chart
-        .draw_series(LineSeries::new(
-            bl_durations_x.clone(),
-            BranchlessStyle::cpu_total_style(),
-        ))?
-        .label("Branchless: Duration")
-        .legend(BranchlessStyle::cpu_total_legend_style());
-        chart.draw_series(PointSeries::of_element(
-            bl_durations_x.clone(),
-            0,
-            BranchlessStyle::cpu_total_style(),
-            &BranchingStyle::line_points_circle(),
-        ))?;
+    Ok(())
+}
 
-        chart
+fn draw_durations_chart(
+    drawing_area: &PlotArea<'_>,
+    x_vals: Vec<f64>,
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions { names, x_axis, .. } = *options;
+    let x_range = x_axis.range(&x_vals);
+    let (br_durations_x, bl_durations_x) =
+        branching_branchless_durations_over_x(x_vals, br_perf_stats, bl_perf_stats, options)?;
+
+    let max_duration = max_upper_bound(br_durations_x.iter().chain(&bl_durations_x));
+
+    let mut chart = ChartBuilder::on(drawing_area)
+        .caption("Duration", ("Arial", 30))
+        .set_label_area_size(LabelAreaPosition::Left, 40)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(x_range, 0.0..max_duration * 1.1)?;
+
+    chart
+        .configure_mesh()
+        .x_labels(10)
+        .y_desc(options.normalize.duration_label())
+        .y_labels(10)
+        .x_desc(x_axis.label())
+        .x_label_formatter(&|x| x_axis.format_label(*x))
+        .draw()?;
+    x_axis.draw_markers(&mut chart)?;
+
+    draw_durations(&mut chart, &br_durations_x, &bl_durations_x, names)?;
+
+    draw_error_bars_legend(&mut chart, options)?;
+    chart
         .configure_series_labels()
-        .position(SeriesLabelPosition::MiddleRight)
+        .position(SeriesLabelPosition::UpperRight)
         .border_style(BLACK)
         .background_style(WHITE.mix(0.8))
         .draw()?;
 
+    Ok(())
+}
+
+/// Draw the durations of both kernels with their error bars, in the durations panel and along with the branch misses
+fn draw_durations(
+    chart: &mut XyChart<'_, '_>,
+    br_durations_x: &SeriesOverX,
+    bl_durations_x: &SeriesOverX,
+    names: &KernelNames,
+) -> Result<(), Box<dyn Error>> {
+    draw_series_with_points(
+        chart,
+        br_durations_x,
+        4,
+        BranchingStyle::cpu_total_style(),
+        format!("{}: Duration", names.baseline),
+        BranchingStyle::line_points_circle(),
+    )?;
+    draw_series_with_points(
+        chart,
+        bl_durations_x,
+        5,
+        BranchlessStyle::cpu_total_style(),
+        format!("{}: Duration", names.candidate),
+        BranchlessStyle::line_points_triangle(),
+    )
+}
+
+fn draw_branch_misses_chart(
+    drawing_area: &PlotArea<'_>,
+    x_vals: Vec<f64>,
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions { names, x_axis, .. } = *options;
+    let x_range = x_axis.range(&x_vals);
+    let (br_frac_misses_x, br_extrapolated) =
+        frac_branch_misses_over_x(&x_vals, br_perf_stats, options)?;
+    let (bl_frac_misses_x, bl_extrapolated) =
+        frac_branch_misses_over_x(&x_vals, bl_perf_stats, options)?;
+    // The fractions are scaled back up to percent as this panel doesn't share its y-axis with the durations
+    let to_pct = |series: SeriesOverX| -> SeriesOverX {
+        series
+            .into_iter()
+            .map(|(x, frac)| (x, frac.scale(100.)))
+            .collect()
+    };
+    let br_misses_pct_x = to_pct(br_frac_misses_x);
+    let bl_misses_pct_x = to_pct(bl_frac_misses_x);
+
+    let max_pct = max_upper_bound(br_misses_pct_x.iter().chain(&bl_misses_pct_x));
+
+    let mut chart = ChartBuilder::on(drawing_area)
+        .caption("Branch Misses", ("Arial", 30))
+        .set_label_area_size(LabelAreaPosition::Left, 40)
+        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .build_cartesian_2d(x_range, 0.0..(max_pct * 1.1).max(1.0))?;
+
+    chart
+        .configure_mesh()
+        .x_labels(10)
+        .y_desc("Branch misses [% of all branches]")
+        .y_labels(10)
+        .x_desc(x_axis.label())
+        .x_label_formatter(&|x| x_axis.format_label(*x))
+        .draw()?;
+    x_axis.draw_markers(&mut chart)?;
+
+    chart
+        .draw_series(LineSeries::new(means(&br_misses_pct_x), RED))?
+        .label(format!("{}: Branch Misses", names.baseline))
+        .legend(BranchingStyle::branch_misses_legend_style());
+    chart.draw_series(PointSeries::of_element(
+        means(&br_misses_pct_x),
+        3,
+        RED,
+        &BranchingStyle::line_points_circle(),
+    ))?;
+    draw_error_bars(&mut chart, &br_misses_pct_x, RED)?;
+
+    chart
+        .draw_series(LineSeries::new(means(&bl_misses_pct_x), PURPLE))?
+        .label(format!("{}: Branch Misses", names.candidate))
+        .legend(BranchlessStyle::branch_misses_legend_style());
+    chart.draw_series(PointSeries::of_element(
+        means(&bl_misses_pct_x),
+        3,
+        PURPLE,
+        &BranchlessStyle::line_points_triangle(),
+    ))?;
+    draw_error_bars(&mut chart, &bl_misses_pct_x, PURPLE)?;
+    draw_extrapolated_points(
+        &mut chart,
+        &[
+            extrapolated_points(&br_misses_pct_x, &br_extrapolated),
+            extrapolated_points(&bl_misses_pct_x, &bl_extrapolated),
+        ]
+        .concat(),
+    )?;
+
+    draw_error_bars_legend(&mut chart, options)?;
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()?;
 
     Ok(())
 }
 
-fn frac_branch_misses_core_from_perf_stats(
+/// The fraction of the branches that were mispredicted on all core PMUs together, at every x it was counted at
+///
+/// perf only derives the fraction per PMU, so it's computed from the counts, falling back to perf's metric if the
+/// branches weren't counted. PMUs with low coverage are left out like in the other totals.
+fn frac_branch_misses_over_x(
+    x_vals: &[f64],
     perf_stat_runs: &[Vec<PerfStatRecord>],
-) -> Result<Vec<f64>, Box<dyn Error>> {
-    let mut vals: Vec<f64> = vec![];
-    for perf_stats in perf_stat_runs {
-        for data in perf_stats {
-            if data.event.contains("cpu_core/branch-misses") {
-                if data.counter_value == "<not counted>" {
-                    vals.push(0.);
-                } else {
-                    let v: f64 = data.metric_value.parse()?;
-                    vals.push(v / 100.);
+    options: &PlotOptions,
+) -> Result<(SeriesOverX, Extrapolated), Box<dyn Error>> {
+    let branch_misses: Event = "branch-misses".parse()?;
+    let branches: Event = "branches".parse()?;
+    let (misses_x, mut extrapolated) =
+        total_over_x(x_vals, perf_stat_runs, &branch_misses, options);
+    let (branches_x, branches_extrapolated) =
+        total_over_x(x_vals, perf_stat_runs, &branches, options);
+    let at = |series: &SeriesOverX, x: f64| series.iter().find(|(sx, _)| *sx == x).map(|(_, e)| *e);
+
+    let series = x_vals
+        .iter()
+        .zip(perf_stat_runs)
+        .filter_map(|(x, records)| {
+            let misses = at(&misses_x, *x)?;
+            let frac = match at(&branches_x, *x) {
+                Some(branches) if branches.mean > 0. => misses.mean / branches.mean,
+                _ => {
+                    let record = records.iter().find(|r| {
+                        r.event.matches(&branch_misses) && options.coverage.is_summed(r)
+                    })?;
+                    record.metric.as_ref()?.value / 100.
                 }
-            }
+            };
+            // perf doesn't report the variance of metrics, so use the relative error of the misses
+            Some((
+                *x,
+                Estimate::new(frac, frac * misses.rel_sem_pct() / 100., misses.runs),
+            ))
+        })
+        .collect();
+
+    // A point is extrapolated if either of the counts is
+    for (x, pcnt_running) in branches_extrapolated {
+        match extrapolated.iter_mut().find(|(ex, _)| *ex == x) {
+            Some((_, lowest)) => *lowest = lowest.min(pcnt_running),
+            None => extrapolated.push((x, pcnt_running)),
         }
     }
-    Ok(vals)
+    Ok((series, extrapolated))
 }
 
+/// The durations in the unit of the normalization, see [`Normalize::duration_label`]
 fn durations_from_perf_stats(
     perf_stat_runs: &[Vec<PerfStatRecord>],
-) -> Result<Vec<f64>, Box<dyn Error>> {
-    let mut vals: Vec<f64> = vec![];
-    for perf_stats in perf_stat_runs {
+    options: &PlotOptions,
+) -> Result<Vec<Estimate>, Box<dyn Error>> {
+    let duration_time: Event = "duration_time".parse()?;
+    let (_, scale) = options.normalize.duration_unit();
+    let mut vals: Vec<Estimate> = vec![];
+    for (i, perf_stats) in perf_stat_runs.iter().enumerate() {
         for data in perf_stats {
-            if data.event.contains("duration_time") {
-                if data.counter_value == "<not counted>" {
-                    vals.push(0.);
-                } else {
-                    let v = data.counter_value.split('.').next().unwrap();
-                    let v: u64 = v.parse()?;
-                    vals.push((v as f64) / 1_000_000_000.);
-                }
+            if data.event.matches(&duration_time) {
+                vals.push(estimate(data, options.repeat_at(i)).scale(scale));
             }
         }
     }
     Ok(vals)
 }
 
-type BrBlDurationsOverX = (Vec<(f64, f64)>, Vec<(f64, f64)>);
+type BrBlDurationsOverX = (SeriesOverX, SeriesOverX);
 fn branching_branchless_durations_over_x(
-    ratio_vals: Vec<f64>,
+    x_vals: Vec<f64>,
     br_perf_stats: &[Vec<PerfStatRecord>],
     bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
 ) -> Result<BrBlDurationsOverX, Box<dyn Error>> {
-    let br_durations_ms = durations_from_perf_stats(br_perf_stats)?;
-    let bl_durations_ms = durations_from_perf_stats(bl_perf_stats)?;
+    let br_durations_ms = durations_from_perf_stats(br_perf_stats, options)?;
+    let bl_durations_ms = durations_from_perf_stats(bl_perf_stats, options)?;
 
-    let br_durations_x: Vec<(f64, f64)> = ratio_vals
-        .clone()
-        .into_iter()
-        .zip(br_durations_ms)
-        .collect();
-    let bl_durations_x: Vec<(f64, f64)> = ratio_vals
-        .clone()
-        .into_iter()
-        .zip(bl_durations_ms)
-        .collect();
+    let br_durations_x: SeriesOverX = x_vals.clone().into_iter().zip(br_durations_ms).collect();
+    let bl_durations_x: SeriesOverX = x_vals.clone().into_iter().zip(bl_durations_ms).collect();
 
     Ok((br_durations_x, bl_durations_x))
 }
 
 fn cpu_instructions_plot_view(
-    save_to: &'static OsStr,
-    ratio_vals: Vec<f64>,
+    save_to: &OsStr,
+    x_vals: Vec<f64>,
     br_perf_stats: &[Vec<PerfStatRecord>],
     bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
 ) -> Result<(), Box<dyn Error>> {
-    let (br_core_vals, br_min_mag, br_core_max) =
-        vals_from_perf_stats(br_perf_stats, "cpu_core/instructions")?;
-    let (bl_core_vals, bl_min_mag, bl_core_max) =
-        vals_from_perf_stats(bl_perf_stats, "cpu_core/instructions")?;
-
-    let (br_atom_vals, _, br_atom_max) =
-        vals_from_perf_stats(br_perf_stats, "cpu_atom/instructions")?;
-    let (bl_atom_vals, _, bl_atom_max) =
-        vals_from_perf_stats(bl_perf_stats, "cpu_atom/instructions")?;
-
-    let min_magnitude = br_min_mag.min(bl_min_mag);
-
-    let br_core_scaled = br_core_vals.into_iter().map(|x| x / min_magnitude.scale());
-    let br_atom_scaled = br_atom_vals.into_iter().map(|x| x / min_magnitude.scale());
-    let bl_core_scaled = bl_core_vals.into_iter().map(|x| x / min_magnitude.scale());
-    let bl_atom_scaled = bl_atom_vals.into_iter().map(|x| x / min_magnitude.scale());
-
-    let max_scaled =
-        (br_core_max + br_atom_max).max(bl_core_max + bl_atom_max) as f64 / min_magnitude.scale();
-
-    let br_total_scaled: Vec<f64> = br_core_scaled
-        .clone()
-        .zip(br_atom_scaled.clone())
-        .map(|(a, b)| a + b)
-        .collect();
-    let br_total_x: Vec<(f64, f64)> = ratio_vals
-        .clone()
-        .into_iter()
-        .zip(br_total_scaled)
-        .collect();
-    let br_core_x: Vec<(f64, f64)> = ratio_vals.clone().into_iter().zip(br_core_scaled).collect();
-    let br_atom_x: Vec<(f64, f64)> = ratio_vals.clone().into_iter().zip(br_atom_scaled).collect();
-
-    // What to do about perf stat not counting cycles from the ATOM cpus?
-    // br_atom_x.retain(|(_, a)| *a != 0.0);
-
-    let bl_total_scaled: Vec<f64> = bl_core_scaled
-        .clone()
-        .zip(bl_atom_scaled.clone())
-        .map(|(a, b)| a + b)
-        .collect();
-    let bl_total_x: Vec<(f64, f64)> = ratio_vals
-        .clone()
-        .into_iter()
-        .zip(bl_total_scaled)
-        .collect();
-    let bl_core_x: Vec<(f64, f64)> = ratio_vals.clone().into_iter().zip(bl_core_scaled).collect();
-    let bl_atom_x: Vec<(f64, f64)> = ratio_vals.into_iter().zip(bl_atom_scaled).collect();
-    // What to do about perf stat not counting cycles from the ATOM cpus?
-    // bl_atom_x.retain(|(_, b)| *b != 0.0);
-
+    let PlotOptions { x_axis, .. } = *options;
     let root_drawing_area = SVGBackend::new(save_to, (1024, 768)).into_drawing_area();
 
     root_drawing_area.fill(&WHITE).unwrap();
 
-    let mut chart = ChartBuilder::on(&root_drawing_area)
-        .caption("CPU Instructions vs. True/False ratio", ("Arial", 30))
+    draw_cpu_instructions_chart(
+        &root_drawing_area,
+        &format!("CPU Instructions vs. {}", x_axis.name),
+        x_vals,
+        br_perf_stats,
+        bl_perf_stats,
+        options,
+    )
+}
+
+fn draw_cpu_instructions_chart(
+    drawing_area: &PlotArea<'_>,
+    caption: &str,
+    x_vals: Vec<f64>,
+    br_perf_stats: &[Vec<PerfStatRecord>],
+    bl_perf_stats: &[Vec<PerfStatRecord>],
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error>> {
+    let PlotOptions {
+        names,
+        x_axis,
+        coverage,
+        ..
+    } = *options;
+    let x_range = x_axis.range(&x_vals);
+    let instructions: Event = "instructions".parse()?;
+    let records = || br_perf_stats.iter().chain(bl_perf_stats).flatten();
+    // The instructions of each core PMU in the records, e.g. `cpu_core` and `cpu_atom` on a hybrid CPU
+    let pmu_events: Vec<Event> = distinct_events(records())
+        .into_iter()
+        .filter(|event| event.matches(&instructions))
+        .collect();
+    let summed_pmus = pmu_events
+        .iter()
+        .filter(|event| {
+            records().any(|r| {
+                r.event == **event && r.counter_value.counted().is_some() && coverage.is_summed(r)
+            })
+        })
+        .count();
+
+    let (br_total_x, br_total_extrapolated) =
+        total_over_x(&x_vals, br_perf_stats, &instructions, options);
+    let (bl_total_x, bl_total_extrapolated) =
+        total_over_x(&x_vals, bl_perf_stats, &instructions, options);
+
+    let min = br_total_x
+        .iter()
+        .chain(&bl_total_x)
+        .map(|(_, e)| e.mean)
+        .fold(f64::INFINITY, f64::min);
+    let min_magnitude = Magnitude::of_axis(min);
+    let scale = 1. / min_magnitude.scale();
+    let scaled = |series: SeriesOverX| -> SeriesOverX {
+        series
+            .into_iter()
+            .map(|(x, e)| (x, e.scale(scale)))
+            .collect()
+    };
+    let pmu_series: Vec<_> = pmu_events
+        .iter()
+        .map(|event| {
+            (
+                event,
+                event_over_x(&x_vals, br_perf_stats, event, options),
+                event_over_x(&x_vals, bl_perf_stats, event, options),
+            )
+        })
+        .collect();
+    // A PMU left out of the total can still be above it
+    let max_scaled = max_upper_bound(
+        br_total_x.iter().chain(&bl_total_x).chain(
+            pmu_series
+                .iter()
+                .flat_map(|(_, (br, _), (bl, _))| br.iter().chain(bl)),
+        ),
+    ) * scale;
+
+    let mut chart = ChartBuilder::on(drawing_area)
+        .caption(caption, ("Arial", 30))
         .set_label_area_size(LabelAreaPosition::Left, 40)
         .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .build_cartesian_2d(0.0..103.0, 0.0..max_scaled)?;
+        .build_cartesian_2d(x_range, 0.0..max_scaled.max(1.0))?;
 
     chart
         .configure_mesh()
         .x_labels(10)
         .y_desc(format!(
-            "CPU Instructions {unit}",
-            unit = match min_magnitude {
-                Magnitude::E3 => "10 ^ 3".to_string(),
-                Magnitude::E6 => "10 ^ 6".to_string(),
-                Magnitude::E9 => "10 ^ 9".to_string(),
-                Magnitude::E12 => "10 ^ 12".to_string(),
-                Magnitude::E15
-                | Magnitude::E18
-                | Magnitude::E21
-                | Magnitude::E24
-                | Magnitude::E27 => format!("{min_magnitude}"),
-                Magnitude::Eminus9 | Magnitude::Eminus6 | Magnitude::Eminus3 | Magnitude::E0 =>
-                    String::new(),
-            }
+            "CPU Instructions{} {}",
+            options.normalize.per(&instructions),
+            min_magnitude.axis_label()
         ))
         .y_labels(10)
-        .x_desc("True/False ratio [%]")
+        .x_desc(x_axis.label())
+        .x_label_formatter(&|x| x_axis.format_label(*x))
         .draw()?;
-
-    chart
-        .draw_series(LineSeries::new(
-            br_total_x.clone(),
+    x_axis.draw_markers(&mut chart)?;
+
+    let mut extrapolated = vec![];
+    // With a single PMU in the total it's the same line
+    if summed_pmus > 1 {
+        let br_total_x = scaled(br_total_x);
+        let bl_total_x = scaled(bl_total_x);
+        draw_series_with_points(
+            &mut chart,
+            &br_total_x,
+            10,
             BranchingStyle::cpu_total_style(),
-        ))?
-        .label("Branching CPU Total")
-        .legend(BranchingStyle::cpu_total_legend_style());
-    chart.draw_series(PointSeries::of_element(
-        br_total_x.clone(),
-        10,
-        BranchingStyle::cpu_total_style(),
-        &BranchingStyle::line_points_circle(),
-    ))?;
-
-    chart
-        .draw_series(LineSeries::new(
-            br_core_x.clone(),
-            BranchingStyle::cpu_core_style(),
-        ))?
-        .label("Branching CPU Core")
-        .legend(BranchingStyle::cpu_core_legend_style());
-    chart.draw_series(PointSeries::of_element(
-        br_core_x.clone(),
-        4,
-        BranchingStyle::cpu_core_style(),
-        &BranchingStyle::line_points_circle(),
-    ))?;
-
-    chart
-        .draw_series(LineSeries::new(
-            br_atom_x.clone(),
-            BranchingStyle::cpu_atom_style(),
-        ))?
-        .label("Branching CPU Atom")
-        .legend(BranchingStyle::cpu_atom_legend_style());
-    chart.draw_series(PointSeries::of_element(
-        br_atom_x.clone(),
-        4,
-        BranchingStyle::cpu_atom_style(),
-        &BranchingStyle::line_points_circle(),
-    ))?;
-
-    chart
-        .draw_series(LineSeries::new(
-            bl_total_x.clone(),
+            format!("{} CPU Total", names.baseline),
+            BranchingStyle::line_points_circle(),
+        )?;
+        draw_series_with_points(
+            &mut chart,
+            &bl_total_x,
+            12,
             BranchlessStyle::cpu_total_style(),
-        ))?
-        .label("Branchless CPU Total")
-        .legend(BranchlessStyle::cpu_total_legend_style());
-    chart.draw_series(PointSeries::of_element(
-        bl_total_x.into_iter(),
-        12,
-        BranchlessStyle::cpu_total_style(),
-        &BranchlessStyle::line_points_triangle(),
-    ))?;
-
-    chart
-        .draw_series(LineSeries::new(
-            bl_core_x.clone(),
-            BranchlessStyle::cpu_core_style(),
-        ))?
-        .label("Branchless CPU Core")
-        .legend(BranchlessStyle::cpu_core_legend_style());
-    chart.draw_series(PointSeries::of_element(
-        bl_core_x.into_iter(),
-        5,
-        BranchlessStyle::cpu_core_style(),
-        &BranchlessStyle::line_points_triangle(),
-    ))?;
+            format!("{} CPU Total", names.candidate),
+            BranchlessStyle::line_points_triangle(),
+        )?;
+        extrapolated.extend(extrapolated_points(&br_total_x, &br_total_extrapolated));
+        extrapolated.extend(extrapolated_points(&bl_total_x, &bl_total_extrapolated));
+    }
 
-    chart
-        .draw_series(LineSeries::new(
-            bl_atom_x.clone(),
-            BranchlessStyle::cpu_atom_style(),
-        ))?
-        .label("Branchless CPU Atom")
-        .legend(BranchlessStyle::cpu_atom_legend_style());
-    chart.draw_series(PointSeries::of_element(
-        bl_atom_x.into_iter(),
-        5,
-        BranchlessStyle::cpu_atom_style(),
-        &BranchlessStyle::line_points_triangle(),
-    ))?;
+    for (index, (event, (br_x, br_extrapolated), (bl_x, bl_extrapolated))) in
+        pmu_series.into_iter().enumerate()
+    {
+        // Without hybrid cores perf doesn't name the PMU, which is then called `cpu`
+        let pmu = event.pmu.as_deref().unwrap_or("cpu");
+        // Excluded for their coverage at every x
+        if !br_x.is_empty() {
+            let br_x = scaled(br_x);
+            draw_series_with_points(
+                &mut chart,
+                &br_x,
+                4,
+                coverage.line_style(BranchingStyle::cpu_pmu_style(index), &br_extrapolated),
+                format!("{} {pmu}", names.baseline),
+                BranchingStyle::line_points_circle(),
+            )?;
+            extrapolated.extend(extrapolated_points(&br_x, &br_extrapolated));
+        }
+        if !bl_x.is_empty() {
+            let bl_x = scaled(bl_x);
+            draw_series_with_points(
+                &mut chart,
+                &bl_x,
+                5,
+                coverage.line_style(BranchlessStyle::cpu_pmu_style(index), &bl_extrapolated),
+                format!("{} {pmu}", names.candidate),
+                BranchlessStyle::line_points_triangle(),
+            )?;
+            extrapolated.extend(extrapolated_points(&bl_x, &bl_extrapolated));
+        }
+    }
+    draw_extrapolated_points(&mut chart, &extrapolated)?;
 
+    draw_error_bars_legend(&mut chart, options)?;
     chart
         .configure_series_labels()
         .position(SeriesLabelPosition::UpperRight)
@@ -905,3 +1774,61 @@ fn cpu_instructions_plot_view(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{SweepParam, XScale},
+        perf_stat::parse_perf_stat_json,
+    };
+
+    #[test]
+    fn classify_values_below_the_smallest_magnitude() {
+        assert_eq!(Magnitude::classify(3e-10), Magnitude::Eminus9);
+        assert_eq!(Magnitude::classify(-3e-12), Magnitude::Eminus9);
+        assert_eq!(Magnitude::of_axis(0.85), Magnitude::E0);
+        assert_eq!(Magnitude::of_axis(3e-10), Magnitude::Eminus9);
+    }
+
+    #[test]
+    fn plot_rare_event_per_iteration() {
+        let run = |switches: u32| {
+            parse_perf_stat_json(&format!(
+                r#"{{"counter-value" : "{switches}.000000", "unit" : "", "event" : "context-switches:u", "event-runtime" : 700000000, "pcnt-running" : 100.00, "metric-value" : "0.000000", "metric-unit" : "/sec"}}"#
+            ))
+            .unwrap()
+        };
+        let x_vals = vec![0., 100.];
+        let x_axis = SweepParam::Ratio.x_axis(&x_vals, XScale::Linear);
+        let coverage = Coverage {
+            min_pcnt_running: 10.,
+            low_coverage: LowCoverage::DeEmphasize,
+        };
+        let options = PlotOptions {
+            names: &KernelNames {
+                baseline: "branch",
+                candidate: "branchless",
+            },
+            x_axis: &x_axis,
+            coverage: &coverage,
+            normalize: Normalize::PerIteration,
+            repeats: &[],
+        };
+        let save_to = std::env::temp_dir().join("rust-perf-comp-rare-event-per-iteration.svg");
+        // 3 context switches in 10^8 elements times the passes over them
+        plot_events_vs_x(
+            x_vals,
+            &[run(3), run(5)],
+            &[run(3), run(4)],
+            &[Some(100_000_000), Some(100_000_000)],
+            &options,
+            &["context-switches".parse().unwrap()],
+            save_to.as_os_str(),
+        )
+        .unwrap();
+
+        let svg = std::fs::read_to_string(&save_to).unwrap();
+        assert!(svg.contains("context-switches per iteration [10 ^ -9]"));
+    }
+}
@@ -0,0 +1,312 @@
+use crate::perf_stat::PerfStatRecord;
+
+/// A mean over a number of runs along with its standard error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub mean: f64,
+    /// Standard error of the mean
+    pub sem: f64,
+    /// Number of runs the mean is computed from
+    pub runs: u32,
+}
+
+impl Estimate {
+    pub fn new(mean: f64, sem: f64, runs: u32) -> Self {
+        Self { mean, sem, runs }
+    }
+
+    /// Estimate from a record of `perf stat --repeat <runs>`
+    ///
+    /// perf reports the `variance` as the standard error of the mean relative to the mean, in percent.
+    pub fn from_record(record: &PerfStatRecord, runs: u32) -> Option<Self> {
+        let mean = record.counter_value.counted()?;
+        let rel_sem = record.variance.unwrap_or(0.) as f64 / 100.;
+        Some(Self::new(mean, (mean * rel_sem).abs(), runs))
+    }
+
+    /// Estimate the mean of independent samples, `None` if there are none
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let sem = if samples.len() < 2 {
+            0.
+        } else {
+            let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.);
+            (variance / n).sqrt()
+        };
+        Some(Self::new(mean, sem, samples.len() as u32))
+    }
+
+    /// The standard error relative to the mean, in percent
+    pub fn rel_sem_pct(&self) -> f64 {
+        if self.mean == 0. {
+            0.
+        } else {
+            (self.sem / self.mean).abs() * 100.
+        }
+    }
+
+    /// The standard deviation of the runs relative to the mean, in percent
+    pub fn rel_stddev_pct(&self) -> f64 {
+        self.rel_sem_pct() * (self.runs as f64).sqrt()
+    }
+
+    /// The mean of `self / other`, propagating the errors assuming the two are independent
+    pub fn ratio(&self, other: &Estimate) -> Estimate {
+        let mean = self.mean / other.mean;
+        let sem = ((self.sem / other.mean).powi(2)
+            + (self.mean * other.sem / other.mean.powi(2)).powi(2))
+        .sqrt();
+        Estimate::new(mean, sem, self.runs.min(other.runs))
+    }
+
+    /// Scale the mean and the error by a constant
+    pub fn scale(&self, factor: f64) -> Estimate {
+        Estimate::new(self.mean * factor, (self.sem * factor).abs(), self.runs)
+    }
+
+    /// The bounds of the 95% confidence interval of the mean, from Student's t-distribution with the number of runs
+    ///
+    /// Without the number of runs it's ±1.96 standard errors, the interval for a large number of runs.
+    pub fn ci95(&self) -> (f64, f64) {
+        let t = if self.runs >= 2 {
+            students_t_critical_value(0.05, (self.runs - 1) as f64)
+        } else {
+            1.96
+        };
+        (self.mean - t * self.sem, self.mean + t * self.sem)
+    }
+}
+
+/// The sum of two independent estimates, e.g. the same event counted on two PMUs
+impl std::ops::Add for Estimate {
+    type Output = Estimate;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Estimate::new(
+            self.mean + rhs.mean,
+            (self.sem.powi(2) + rhs.sem.powi(2)).sqrt(),
+            self.runs.min(rhs.runs),
+        )
+    }
+}
+
+/// Remove the samples outside of Tukey's fences, i.e. more than 1.5 interquartile ranges outside the quartiles
+///
+/// Returns the remaining samples in ascending order.
+pub fn reject_outliers(samples: &[f64]) -> Vec<f64> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.len() < 4 {
+        return sorted;
+    }
+    let (q1, q3) = (quantile(&sorted, 0.25), quantile(&sorted, 0.75));
+    let iqr = q3 - q1;
+    let (low, high) = (q1 - 1.5 * iqr, q3 + 1.5 * iqr);
+    sorted.retain(|s| (low..=high).contains(s));
+    sorted
+}
+
+/// The `q` quantile of sorted samples, linearly interpolated between the closest ranks
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+/// Result of Welch's unequal variances t-test
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WelchTest {
+    pub t: f64,
+    pub degrees_of_freedom: f64,
+    /// Two-sided p-value
+    pub p: f64,
+}
+
+/// Welch's t-test for the difference between two means, `None` if there's no variance to test with
+pub fn welch_t_test(a: &Estimate, b: &Estimate) -> Option<WelchTest> {
+    if a.runs < 2 || b.runs < 2 {
+        return None;
+    }
+    let (va, vb) = (a.sem.powi(2), b.sem.powi(2));
+    if va + vb == 0. {
+        return None;
+    }
+    let t = (a.mean - b.mean) / (va + vb).sqrt();
+    let degrees_of_freedom =
+        (va + vb).powi(2) / (va.powi(2) / (a.runs - 1) as f64 + vb.powi(2) / (b.runs - 1) as f64);
+    let p = students_t_two_sided_p(t, degrees_of_freedom);
+    Some(WelchTest {
+        t,
+        degrees_of_freedom,
+        p,
+    })
+}
+
+/// The t with P(|T| > t) = `alpha` for Student's t-distribution with `df` degrees of freedom, found by bisection
+fn students_t_critical_value(alpha: f64, df: f64) -> f64 {
+    let (mut low, mut high) = (0., 1000.);
+    for _ in 0..100 {
+        let t = (low + high) / 2.;
+        if students_t_two_sided_p(t, df) > alpha {
+            low = t;
+        } else {
+            high = t;
+        }
+    }
+    (low + high) / 2.
+}
+
+/// P(|T| > |t|) for Student's t-distribution with `df` degrees of freedom
+fn students_t_two_sided_p(t: f64, df: f64) -> f64 {
+    regularized_incomplete_beta(df / (df + t * t), df / 2., 0.5)
+}
+
+/// I_x(a, b) evaluated with the continued fraction from Numerical Recipes
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1. - x).ln();
+    // The continued fraction converges fastest for x < (a + 1) / (a + b + 2)
+    if x < (a + 1.) / (a + b + 2.) {
+        ln_front.exp() * beta_continued_fraction(x, a, b) / a
+    } else {
+        1. - ln_front.exp() * beta_continued_fraction(1. - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 300;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.;
+    let mut d = 1. - (a + b) * x / (a + 1.);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1. / d;
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2. * m;
+
+        let aa = m * (b - m) * x / ((a + m2 - 1.) * (a + m2));
+        d = 1. + aa * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1. + aa / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1. / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.));
+        d = 1. + aa * d;
+        d = if d.abs() < TINY { TINY } else { d };
+        c = 1. + aa / c;
+        c = if c.abs() < TINY { TINY } else { c };
+        d = 1. / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.).abs() < EPSILON {
+            break;
+        }
+    }
+    h
+}
+
+/// The Lanczos approximation of ln(Γ(x)) for x > 0
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1. + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn students_t_p_values() {
+        // The two-sided critical values at 5% from a t table
+        assert_close(students_t_two_sided_p(2.776, 4.), 0.05, 1e-4);
+        assert_close(students_t_two_sided_p(2.228, 10.), 0.05, 1e-4);
+        assert_close(students_t_two_sided_p(-2.228, 10.), 0.05, 1e-4);
+        assert_close(students_t_two_sided_p(1.96, 1e6), 0.05, 1e-4);
+        assert_close(students_t_two_sided_p(2., 10.), 0.0734, 1e-4);
+        assert_close(students_t_two_sided_p(0., 10.), 1., 1e-12);
+    }
+
+    #[test]
+    fn students_t_critical_values() {
+        assert_close(students_t_critical_value(0.05, 4.), 2.776, 1e-3);
+        assert_close(students_t_critical_value(0.05, 10.), 2.228, 1e-3);
+        assert_close(students_t_critical_value(0.01, 30.), 2.750, 1e-3);
+    }
+
+    #[test]
+    fn ln_gamma_of_integers() {
+        assert_close(ln_gamma(1.), 0., 1e-10);
+        assert_close(ln_gamma(5.), 24f64.ln(), 1e-10);
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), 1e-10);
+    }
+
+    #[test]
+    fn welch_t_test_of_unequal_variances() {
+        let a = Estimate::new(10., 1., 5);
+        let b = Estimate::new(12., 2., 5);
+        let test = welch_t_test(&a, &b).unwrap();
+        assert_close(test.t, -2. / 5f64.sqrt(), 1e-12);
+        // (1 + 4)^2 / (1^2 / 4 + 4^2 / 4)
+        assert_close(test.degrees_of_freedom, 25. / 4.25, 1e-12);
+        assert_close(test.p, students_t_two_sided_p(test.t, 25. / 4.25), 1e-12);
+        assert!(test.p > 0.05);
+    }
+
+    #[test]
+    fn welch_t_test_needs_variance() {
+        assert_eq!(
+            welch_t_test(&Estimate::new(1., 0., 5), &Estimate::new(2., 0., 5)),
+            None
+        );
+        assert_eq!(
+            welch_t_test(&Estimate::new(1., 1., 1), &Estimate::new(2., 1., 5)),
+            None
+        );
+    }
+
+    #[test]
+    fn ci95_of_five_runs() {
+        let (lower, upper) = Estimate::new(10., 1., 5).ci95();
+        assert_close(lower, 10. - 2.776, 1e-3);
+        assert_close(upper, 10. + 2.776, 1e-3);
+        let (lower, _) = Estimate::new(10., 1., 0).ci95();
+        assert_close(lower, 10. - 1.96, 1e-12);
+    }
+}
@@ -0,0 +1,581 @@
+use crate::{
+    cache::{cache_levels, straddling_sizes},
+    config::{Backend, EventSet, PlotType, SweepArgs, SweepParam, XScale},
+    element::ElementType,
+    kernel::{find_kernel, kernel_names, KERNELS},
+    measure::{record_from_samples, Measure},
+    metadata::RunMetadata,
+    pattern::parse_ratio,
+    perf_event::core_pmus,
+    perf_stat::{parse_perf_stat_json, Event, PerfStatRecord, Phase},
+    phase::ControlFifos,
+    plot_perf_stats::{plot_perf_stats_vs_x, warn_low_coverage, KernelNames, PlotOptions, XAxis},
+};
+use std::{
+    collections::HashSet,
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The events counted on the CPU cores, perf counts them on every core PMU unless they are qualified with one
+pub const CPU_EVENTS: [&str; 4] = ["cycles", "instructions", "branches", "branch-misses"];
+
+impl EventSet {
+    /// The `--event` list for perf stat, e.g. `duration_time,cpu_core/cycles/,...` for `cpu_core`
+    pub fn perf_events(&self) -> String {
+        let cpu_events = CPU_EVENTS.iter().map(|event| match self {
+            EventSet::All => event.to_string(),
+            EventSet::Pmu(pmu) => format!("{pmu}/{event}/"),
+        });
+        std::iter::once(String::from("duration_time"))
+            .chain(cpu_events)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Check that the PMU is one of the core PMUs, if they can be discovered
+    fn check_pmu(&self) -> Result<(), String> {
+        let EventSet::Pmu(pmu) = self else {
+            return Ok(());
+        };
+        let pmus: Vec<String> = core_pmus().into_iter().map(|(name, _)| name).collect();
+        if pmus.is_empty() || pmus.contains(pmu) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unknown PMU '{pmu}', the core PMUs are: {}",
+                pmus.join(", ")
+            ))
+        }
+    }
+}
+
+impl Backend {
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Perf => "perf stat",
+            Backend::Instant => "Instant",
+            Backend::PerfEvent => "perf_event_open",
+        }
+    }
+
+    /// How the runner measures the kernel itself, [`Measure::External`] when perf stat measures it
+    fn runner_measure(&self) -> Measure {
+        match self {
+            Backend::Perf => Measure::External,
+            Backend::Instant => Measure::Instant,
+            Backend::PerfEvent => Measure::PerfEvent,
+        }
+    }
+}
+
+/// The values of a parameter are plotted in log scale by default once they span this many decades
+const LOG_SCALE_DECADES: f64 = 2.;
+
+impl SweepParam {
+    /// Parse a value of the parameter from the command line, an element type is the index of the type in
+    /// [`ElementType::ALL`]
+    pub fn parse_value(&self, s: &str) -> Result<f64, String> {
+        match self {
+            SweepParam::Ratio => return parse_ratio(s),
+            SweepParam::Element => {
+                let element: ElementType = s.parse()?;
+                let index = ElementType::ALL.iter().position(|e| *e == element);
+                return Ok(index.expect("every element type is in ALL") as f64);
+            }
+            _ => (),
+        }
+        let name = self.name();
+        let value: u64 = s
+            .parse()
+            .map_err(|e| format!("invalid {name} '{s}': {e}"))?;
+        // The values end up on the x-axis and in the file names as f64, which is exact up to 2^53
+        if value > 1 << f64::MANTISSA_DIGITS {
+            return Err(format!("the {name} '{s}' is too large to plot exactly"));
+        }
+        if value == 0 && *self == SweepParam::Repeat {
+            return Err(String::from("the kernel has to be measured at least once"));
+        }
+        Ok(value as f64)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SweepParam::Ratio => "ratio",
+            SweepParam::N => "N",
+            SweepParam::Seed => "seed",
+            SweepParam::Repeat => "repeat",
+            SweepParam::Element => "element",
+        }
+    }
+
+    /// The value in the names of the stored files, e.g. `50` in `branch50.json` or `u8` in `branchu8.json`
+    pub fn file_suffix(&self, x: f64) -> String {
+        match self {
+            SweepParam::Element => element_at(x).to_string(),
+            _ => format!("{x}"),
+        }
+    }
+
+    /// The axis the values of the parameter are plotted on
+    ///
+    /// Seeds are never log-scaled automatically, their magnitude means nothing, and the element types are
+    /// categories that are never log-scaled.
+    pub fn x_axis(&self, x_vals: &[f64], scale: XScale) -> XAxis {
+        let (name, unit) = match self {
+            SweepParam::Ratio => ("True/False ratio", Some("%")),
+            SweepParam::N => ("N", Some("elements")),
+            SweepParam::Seed => ("Seed", None),
+            SweepParam::Repeat => ("Repetitions", None),
+            SweepParam::Element => ("Element type", None),
+        };
+        let categories = match self {
+            SweepParam::Element => ElementType::ALL.map(|e| e.to_string()).to_vec(),
+            _ => vec![],
+        };
+        let log = match scale {
+            _ if *self == SweepParam::Element => false,
+            XScale::Auto => *self != SweepParam::Seed && spans_decades(x_vals, LOG_SCALE_DECADES),
+            XScale::Linear => false,
+            XScale::Log => true,
+        };
+        XAxis {
+            name: name.to_string(),
+            unit: unit.map(String::from),
+            log,
+            markers: vec![],
+            categories,
+        }
+    }
+
+    /// The value as it's shown in the progress messages, e.g. `ratio 50%` or `N 4096`
+    fn describe(&self, x: f64) -> String {
+        match self {
+            SweepParam::Ratio => format!("ratio {x}%"),
+            _ => format!("{} {}", self.name(), self.file_suffix(x)),
+        }
+    }
+}
+
+/// The element type at a position on the x-axis, see [`SweepParam::parse_value`]
+fn element_at(x: f64) -> ElementType {
+    ElementType::ALL[x as usize]
+}
+
+/// If the values are positive and the largest is at least `decades` orders of magnitude above the smallest
+fn spans_decades(x_vals: &[f64], decades: f64) -> bool {
+    let min = x_vals.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = x_vals.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    min > 0. && (max / min).log10() >= decades
+}
+
+impl SweepArgs {
+    /// The parameter on the x-axis, the cache sweep is over N
+    fn swept_param(&self) -> SweepParam {
+        if self.cache_sizes {
+            SweepParam::N
+        } else {
+            self.over
+        }
+    }
+
+    /// The JSON directory, or a temporary directory if the runs aren't stored
+    fn metadata_dir(&self) -> PathBuf {
+        self.json_dir.clone().unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("rust-perf-comp-{}-metadata", std::process::id()))
+        })
+    }
+}
+
+/// The parameters of the runs at one value of the swept parameter
+struct SweepPoint {
+    x: f64,
+    ratio: f64,
+    n: usize,
+    seed: u64,
+    repeat: u32,
+    element: ElementType,
+}
+
+impl SweepPoint {
+    /// The fixed parameters from the arguments with the swept one set to `x`
+    fn new(args: &SweepArgs, x: f64) -> Self {
+        let mut point = Self {
+            x,
+            ratio: args.ratio,
+            n: args.n,
+            seed: args.seed,
+            repeat: args.repeat,
+            element: args.element,
+        };
+        match args.swept_param() {
+            SweepParam::Ratio => point.ratio = x,
+            SweepParam::N => point.n = x as usize,
+            SweepParam::Seed => point.seed = x as u64,
+            SweepParam::Repeat => point.repeat = x as u32,
+            SweepParam::Element => point.element = element_at(x),
+        }
+        point
+    }
+}
+
+/// The plots produced by a sweep and the file names they are saved as
+const SWEEP_PLOTS: [(PlotType, &str); 3] = [
+    (PlotType::CpuInstructions, "cpu_instructions_plot.svg"),
+    (PlotType::TimeBranchMisses, "time_branch_misses_plot.svg"),
+    (PlotType::Merged, "merged_plot.svg"),
+];
+
+/// Measure the baseline and every kernel for every value of the swept parameter, then plot each kernel against
+/// the baseline
+pub fn sweep(args: &SweepArgs) -> Result<(), Box<dyn Error>> {
+    if !args.runner.exists() {
+        return Err(format!(
+            "{:?} does not exist - build it with `cargo build --release`",
+            args.runner
+        )
+        .into());
+    }
+    let kernels = kernels_to_sweep(args)?;
+    if args.backend == Backend::Perf {
+        args.events.check_pmu()?;
+    }
+    let (x_vals, x_axis) = if args.cache_sizes {
+        cache_sweep(args)?
+    } else {
+        let x_vals = args
+            .values
+            .iter()
+            .map(|v| args.over.parse_value(v))
+            .collect::<Result<Vec<_>, _>>()?;
+        let x_axis = args.over.x_axis(&x_vals, args.x_scale);
+        (x_vals, x_axis)
+    };
+    std::fs::create_dir_all(&args.save_to)?;
+
+    // The in-process backends measure the phases themselves
+    let fifos = if args.backend == Backend::Perf && !args.no_phase_markers {
+        Some(ControlFifos::create()?)
+    } else {
+        None
+    };
+
+    let result = sweep_and_plot(args, &kernels, &x_vals, &x_axis, fifos.as_ref());
+    if args.json_dir.is_none() {
+        // Only the checksums were needed from the metadata
+        let _ = std::fs::remove_dir_all(args.metadata_dir());
+    }
+    result
+}
+
+/// Measure the baseline and the kernels, and plot each kernel whose checksums match the baseline's
+fn sweep_and_plot(
+    args: &SweepArgs,
+    kernels: &[String],
+    x_vals: &[f64],
+    x_axis: &XAxis,
+    fifos: Option<&ControlFifos>,
+) -> Result<(), Box<dyn Error>> {
+    let repeats: Vec<Option<u32>> = x_vals
+        .iter()
+        .map(|&x| Some(SweepPoint::new(args, x).repeat))
+        .collect();
+    let baseline = sweep_kernel(&args.baseline, args, x_vals, fifos)?;
+    warn_low_coverage(&args.baseline, &baseline.perf_stats, &args.coverage);
+
+    for kernel in kernels {
+        let kernel_sweep = sweep_kernel(kernel, args, x_vals, fifos)?;
+        kernel_sweep.check_checksums(&baseline)?;
+        warn_low_coverage(kernel, &kernel_sweep.perf_stats, &args.coverage);
+
+        let save_dir = if kernels.len() == 1 {
+            args.save_to.clone()
+        } else {
+            args.save_to.join(kernel)
+        };
+        std::fs::create_dir_all(&save_dir)?;
+        let options = PlotOptions {
+            names: &KernelNames {
+                baseline: &args.baseline,
+                candidate: kernel,
+            },
+            x_axis,
+            coverage: &args.coverage,
+            normalize: args.normalize,
+            repeats: &repeats,
+        };
+        let elements: Vec<Option<usize>> = kernel_sweep
+            .metadata
+            .iter()
+            .map(|metadata| Some(metadata.n))
+            .collect();
+        for (plot_type, file_name) in SWEEP_PLOTS {
+            let save_to = save_dir.join(file_name);
+            println!("Saving {save_to:?}");
+            plot_perf_stats_vs_x(
+                x_vals.to_vec(),
+                &baseline.perf_stats,
+                &kernel_sweep.perf_stats,
+                &elements,
+                &options,
+                save_to.as_os_str(),
+                plot_type,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The numbers of elements straddling the cache levels and an axis with the levels marked
+fn cache_sweep(args: &SweepArgs) -> Result<(Vec<f64>, XAxis), Box<dyn Error>> {
+    let levels = cache_levels().map_err(|e| format!("Failed to read the cache sizes: {e}"))?;
+    let bytes_per_element = args.element.bytes_per_element();
+    for level in &levels {
+        println!(
+            "{} holds {} elements",
+            level.label(),
+            level.elements(bytes_per_element)
+        );
+    }
+    let x_vals: Vec<f64> = straddling_sizes(&levels, bytes_per_element)
+        .into_iter()
+        .map(|n| n as f64)
+        .collect();
+    let mut x_axis = SweepParam::N.x_axis(&x_vals, args.x_scale);
+    x_axis.markers = levels
+        .iter()
+        .map(|level| (level.label(), level.elements(bytes_per_element) as f64))
+        .collect();
+    Ok((x_vals, x_axis))
+}
+
+/// The kernels from the arguments in the order they're given, with `all` expanded to every registered kernel
+///
+/// Every kernel is only swept once and the baseline, which is always measured, not again as a kernel.
+fn kernels_to_sweep(args: &SweepArgs) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut kernels: Vec<String> = vec![];
+    for name in &args.kernels {
+        if name == "all" {
+            kernels.extend(KERNELS.iter().map(|k| k.name().to_string()));
+        } else {
+            kernels.push(name.clone());
+        }
+    }
+    for name in kernels.iter().chain([&args.baseline]) {
+        if find_kernel(name).is_none() {
+            return Err(format!(
+                "Unknown kernel '{name}', expected one of: {}",
+                kernel_names().join(", ")
+            )
+            .into());
+        }
+    }
+    let mut seen = HashSet::new();
+    kernels.retain(|kernel| *kernel != args.baseline && seen.insert(kernel.clone()));
+    if kernels.is_empty() {
+        return Err(format!(
+            "No kernel to compare against the baseline '{}', see --kernel",
+            args.baseline
+        )
+        .into());
+    }
+    Ok(kernels)
+}
+
+/// Measure the kernel at every value, storing the records in the JSON directory if there is one
+///
+/// With control FIFOs perf only counts the kernel, and the setup in a separate run if that's measured too. The
+/// metadata of the runs is returned along with the records to check the checksums of the kernels against each other.
+fn sweep_kernel(
+    kernel: &str,
+    args: &SweepArgs,
+    x_vals: &[f64],
+    fifos: Option<&ControlFifos>,
+) -> Result<KernelSweep, Box<dyn Error>> {
+    let metadata_dir = args.metadata_dir();
+    std::fs::create_dir_all(&metadata_dir)?;
+    let mut sweep = KernelSweep {
+        perf_stats: vec![],
+        metadata: vec![],
+    };
+    for &x in x_vals {
+        let point = SweepPoint::new(args, x);
+        let suffix = args.swept_param().file_suffix(x);
+        let mut records = measure(kernel, args, &point, fifos.map(|f| (f, Phase::Kernel)))?;
+        if let Some(fifos) = fifos.filter(|_| args.measure_setup) {
+            records.extend(measure(kernel, args, &point, Some((fifos, Phase::Setup)))?);
+        }
+        let metadata = RunMetadata::read(&metadata_path(&metadata_dir, kernel, &suffix))?;
+        println!(
+            "Achieved a ratio of {:.2}% (requested {}%)",
+            metadata.achieved_ratio, metadata.requested_ratio
+        );
+        if let Some(json_dir) = &args.json_dir {
+            std::fs::write(
+                json_dir.join(format!("{kernel}{suffix}.json")),
+                serde_json::to_string(&records)?,
+            )?;
+        }
+        sweep.perf_stats.push(records);
+        sweep.metadata.push(metadata);
+    }
+    Ok(sweep)
+}
+
+/// The records and metadata of the runs of a kernel at every value of the swept parameter
+struct KernelSweep {
+    perf_stats: Vec<Vec<PerfStatRecord>>,
+    metadata: Vec<RunMetadata>,
+}
+
+impl KernelSweep {
+    /// Fail if the kernel summed to a different checksum than the baseline at any value
+    ///
+    /// A kernel that selects the wrong values can look just as good in the plots, so it's not plotted at all.
+    fn check_checksums(&self, baseline: &KernelSweep) -> Result<(), Box<dyn Error>> {
+        let mismatches: Vec<String> = self
+            .metadata
+            .iter()
+            .zip(&baseline.metadata)
+            .filter_map(|(metadata, reference)| metadata.check_checksum(reference).err())
+            .collect();
+        if !mismatches.is_empty() {
+            return Err(format!("Checksum mismatch:\n{}", mismatches.join("\n")).into());
+        }
+        if let Some((metadata, reference)) = self
+            .metadata
+            .iter()
+            .zip(&baseline.metadata)
+            .find(|(metadata, reference)| !metadata.checksum_is_comparable(reference))
+        {
+            println!(
+                "Warning: could not check all checksums against the baseline, e.g. the {} kernel's {} sum at N {} can't be compared with the {} kernel's",
+                metadata.kernel, metadata.element, metadata.n, reference.kernel
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Measure the kernel with the backend and collect the perf stat records it outputs
+///
+/// If there's a phase marked on the control FIFOs, perf only counts that phase and the records are tagged with it.
+fn measure(
+    kernel: &str,
+    args: &SweepArgs,
+    point: &SweepPoint,
+    marked_phase: Option<(&ControlFifos, Phase)>,
+) -> Result<Vec<PerfStatRecord>, Box<dyn Error>> {
+    let runner = &args.runner;
+    let mut cmd = match args.backend {
+        Backend::Perf => {
+            let mut cmd = Command::new("perf");
+            // Avoid locale specific number formatting such as decimal commas
+            cmd.env("LC_ALL", "C")
+                .arg("stat")
+                .arg("--json-output")
+                .arg(format!("--repeat={}", point.repeat))
+                .arg(format!("--event={}", args.events.perf_events()));
+            if let Some((fifos, _)) = marked_phase {
+                cmd.args(fifos.perf_args());
+            }
+            cmd.arg(runner);
+            if let Some((fifos, phase)) = marked_phase {
+                cmd.args(fifos.runner_args(phase));
+            }
+            cmd
+        }
+        Backend::Instant | Backend::PerfEvent => {
+            let measure = args.backend.runner_measure();
+            let mut cmd = Command::new(runner);
+            cmd.args(["--measure", &measure.to_string()])
+                .args(["--iterations", &point.repeat.to_string()]);
+            cmd
+        }
+    };
+    cmd.args(["--kernel", kernel])
+        .args(["--element", &point.element.to_string()])
+        .args(["--seed", &point.seed.to_string()])
+        .args(["--N", &point.n.to_string()])
+        .args(["--ratio", &point.ratio.to_string()]);
+    cmd.arg("--metadata").arg(metadata_path(
+        &args.metadata_dir(),
+        kernel,
+        &args.swept_param().file_suffix(point.x),
+    ));
+    if args.no_rnd_cmp {
+        cmd.arg("--no-rnd-cmp");
+    } else {
+        cmd.args(["--pattern", &args.pattern.to_string()]);
+    }
+
+    let backend = args.backend.name();
+    let at = args.swept_param().describe(point.x);
+    let what = match marked_phase {
+        Some((_, phase)) => format!("{phase} phase of the {kernel} kernel"),
+        None => format!("{kernel} kernel"),
+    };
+    println!("Measuring the {what} with {backend} at {at}");
+    let output = cmd
+        .output()
+        .map_err(|e| format!("Failed to run {:?}: {e}", cmd.get_program()))?;
+    // Both backends write the records to stderr, the runner doesn't use it unless it fails
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!(
+            "Measuring the {what} with {backend} at {at} failed ({}):\n{stderr}",
+            output.status
+        )
+        .into());
+    }
+
+    let mut records = parse_perf_stat_json(&stderr)?;
+    if let Some((fifos, _)) = marked_phase {
+        replace_duration_time(&mut records, &fifos.take_durations()?)?;
+    }
+    if let Some((_, phase)) = marked_phase {
+        for record in &mut records {
+            record.phase = Some(phase);
+        }
+    }
+    if records.is_empty() {
+        return Err(format!(
+            "Measuring the {what} with {backend} at {at} produced no records:\n{stderr}"
+        )
+        .into());
+    }
+    Ok(records)
+}
+
+/// Where the runner writes the metadata, next to the records of the run if they are stored
+/// Replace perf's `duration_time` with the durations of the phase the runner timed itself, in ns
+///
+/// perf measures the `duration_time` of the whole runner even while its counters are disabled, so with the phase
+/// markers it would include the setup of the vectors.
+fn replace_duration_time(
+    records: &mut Vec<PerfStatRecord>,
+    durations_ns: &[f64],
+) -> Result<(), Box<dyn Error>> {
+    if durations_ns.is_empty() {
+        return Ok(());
+    }
+    let duration_time: Event = "duration_time".parse()?;
+    let phase_time = record_from_samples(
+        duration_time.clone(),
+        "ns",
+        durations_ns,
+        durations_ns.iter().sum::<f64>() as u64,
+        100.,
+    )?;
+    match records.iter_mut().find(|r| r.event.matches(&duration_time)) {
+        Some(record) => *record = phase_time,
+        None => records.push(phase_time),
+    }
+    Ok(())
+}
+
+fn metadata_path(metadata_dir: &Path, kernel: &str, suffix: &str) -> PathBuf {
+    metadata_dir.join(format!("{kernel}{suffix}.meta.json"))
+}